@@ -0,0 +1,170 @@
+use crate::game::{ActiveRun, BeatReached, MilestoneReached, PlayerHit};
+use crate::levels::{CurrentLevel, Speeds};
+use crate::settings::Settings;
+use crate::sim::Rotation;
+use crate::GameState;
+use bevy::prelude::*;
+use std::f32::consts::TAU;
+
+const BEAT_ZOOM: f32 = 0.03;            // zoomed in on a beat, as a fraction
+const STAGE_ZOOM: f32 = 0.15;           // zoomed in on a new stage
+const ZOOM_DECAY: f32 = 6.0;            // per second
+const SHAKE_SECONDS: f32 = 0.6;
+const SHAKE_DISTANCE: f32 = 20.0;       // pixels at full strength
+const SHAKE_FREQUENCY: f32 = 25.0;      // hertz
+const SPIN_SQUASH: f32 = 0.1;           // squash at full spin tilt
+const WOBBLE_ROLL: f32 = 3.0;           // degrees
+const WOBBLE_SQUASH: f32 = 0.12;
+const WOBBLE_PERIOD: f32 = 7.0;         // seconds
+
+// Where the camera is pushed by the run, on top of looking straight at the
+// arena. `GamePlugin`'s events kick the effects off and they wear off on
+// their own; `CameraPlugin` turns the result into the camera's transform.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct CameraRig {
+    time: f32,
+    zoom: f32,
+    shake: f32,                 // 1.0 on a death, fading to 0.0
+}
+
+// The effects the settings allow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CameraEffects {
+    pub zoom_pulse: bool,
+    pub shake: bool,
+    pub tilt: bool,
+}
+
+pub struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraRig>()
+            .add_systems(
+                Update,
+                (
+                    camera_reset_rig.run_if(resource_added::<ActiveRun>),
+                    camera_events,
+                    camera_apply,
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Playing).or_else(in_state(GameState::GameOver))),
+            )
+            .add_systems(OnEnter(GameState::Menu), reset_camera);
+    }
+}
+
+impl CameraEffects {
+    pub fn from_settings(settings: &Settings) -> CameraEffects {
+        let motion = !settings.reduced_motion;
+        CameraEffects {
+            zoom_pulse: motion && settings.zoom_pulse,
+            shake: motion && settings.screen_shake,
+            tilt: motion && settings.camera_tilt,
+        }
+    }
+}
+
+impl CameraRig {
+    pub fn beat(&mut self) {
+        self.zoom = self.zoom.max(BEAT_ZOOM);
+    }
+
+    pub fn new_stage(&mut self) {
+        self.zoom = STAGE_ZOOM;
+    }
+
+    pub fn hit(&mut self) {
+        self.shake = 1.0;
+    }
+
+    pub fn advance(&mut self, delta_seconds: f32) {
+        self.time += delta_seconds;
+        self.zoom *= (-ZOOM_DECAY * delta_seconds).exp();
+        self.shake = (self.shake - delta_seconds / SHAKE_SECONDS).max(0.0);
+    }
+
+    // The camera's transform, given how far the spin is off its steady speed
+    // (`Rotation::tilt`) and how many degrees the level rolls for that. The
+    // arena wobbles as if seen at a slowly changing angle, which squashing
+    // the rolled view fakes well enough. Only x and y of the translation are
+    // meant to be used.
+    pub fn transform(&self, effects: CameraEffects, spin_tilt: f32, level_tilt: f32) -> Transform {
+        let mut transform = Transform::IDENTITY;
+
+        if effects.tilt {
+            let phase = TAU * self.time / WOBBLE_PERIOD;
+            let roll = level_tilt * spin_tilt + WOBBLE_ROLL * phase.sin();
+            transform.rotation = Quat::from_rotation_z(roll.to_radians());
+            transform.scale.y += SPIN_SQUASH * spin_tilt.abs()
+                + WOBBLE_SQUASH * (1.0 - (0.7 * phase).cos()) / 2.0;
+        }
+
+        if effects.zoom_pulse {
+            transform.scale /= 1.0 + self.zoom;
+        }
+
+        if effects.shake && self.shake > 0.0 {
+            // Two sines at unrelated rates shake without repeating visibly.
+            let phase = TAU * SHAKE_FREQUENCY * self.time;
+            let strength = SHAKE_DISTANCE * self.shake * self.shake;
+            transform.translation.x = strength * phase.sin();
+            transform.translation.y = strength * (1.3 * phase + 1.0).sin();
+        }
+        transform
+    }
+}
+
+fn camera_reset_rig(mut rig: ResMut<CameraRig>) {
+    *rig = CameraRig::default();
+}
+
+fn camera_events(
+    mut beats: EventReader<BeatReached>,
+    mut milestones: EventReader<MilestoneReached>,
+    mut hits: EventReader<PlayerHit>,
+    mut rig: ResMut<CameraRig>,
+) {
+    if beats.read().count() > 0 {
+        rig.beat();
+    }
+    if milestones.read().count() > 0 {
+        rig.new_stage();
+    }
+    if hits.read().count() > 0 {
+        rig.hit();
+    }
+}
+
+fn camera_apply(
+    time: Res<Time>,
+    level: Res<CurrentLevel>,
+    speeds: Res<Speeds>,
+    rotation: Res<Rotation>,
+    settings: Res<Settings>,
+    mut rig: ResMut<CameraRig>,
+    mut query: Query<&mut Transform, With<Camera2d>>,
+) {
+    rig.advance(time.delta_seconds());
+    let effects = CameraEffects::from_settings(&settings);
+    let target = rig.transform(
+        effects,
+        rotation.tilt(speeds.spin_speed),
+        level.0.rotation.tilt,
+    );
+    for mut transform in query.iter_mut() {
+        transform.translation.x = target.translation.x;
+        transform.translation.y = target.translation.y;
+        transform.rotation = target.rotation;
+        transform.scale = target.scale;
+    }
+}
+
+fn reset_camera(mut query: Query<&mut Transform, With<Camera2d>>) {
+    for mut transform in query.iter_mut() {
+        transform.translation.x = 0.0;
+        transform.translation.y = 0.0;
+        transform.rotation = Quat::IDENTITY;
+        transform.scale = Vec3::ONE;
+    }
+}
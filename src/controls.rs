@@ -0,0 +1,578 @@
+use crate::GameState;
+use bevy::input::InputSystem;
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use bevy::window::PrimaryWindow;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CONTROLS_VERSION: u32 = 1;
+pub const CONTROLS_FILE: &str = "controls.ron";
+const STICK_DEADZONE: f32 = 0.15;
+const STICK_THRESHOLD: f32 = 0.5;     // past the deadzone, for menus
+const CONTROLS_ITEMS: usize = ACTIONS.len() + 2;
+const CONTROLS_ITEM_COLOR: Color = Color::BLUE;
+const CONTROLS_HOVER_COLOR: Color = Color::WHITE;
+const CONTROLS_LISTEN_COLOR: Color = Color::YELLOW;
+
+// What the player means, whichever device it came from. Gameplay and menus
+// read these from `ActionState` instead of looking at keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    RotateLeft,
+    RotateRight,
+    Up,
+    Down,
+    Confirm,
+    Back,
+    // also backs out of menus, so one key never needs binding to both
+    Pause,
+}
+
+pub const ACTIONS: [Action; 7] = [
+    Action::RotateLeft,
+    Action::RotateRight,
+    Action::Up,
+    Action::Down,
+    Action::Confirm,
+    Action::Back,
+    Action::Pause,
+];
+
+// What holding both rotate directions at once does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BothPressed {
+    #[default]
+    LastPressed,
+    FirstPressed,
+    Cancel,
+}
+
+// Keyboard keys and gamepad buttons per action. The left stick and the
+// halves of the screen, clicked or touched, always rotate and are not
+// rebindable.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Bindings {
+    version: u32,
+    pub keys: BTreeMap<Action, Vec<KeyCode>>,
+    pub buttons: BTreeMap<Action, Vec<GamepadButtonType>>,
+    #[serde(default)]
+    pub both_pressed: BothPressed,
+    // where the bindings are saved; `None` keeps them in memory only
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+// Actions held this frame, and those that were not held the frame before.
+// Held actions have a strength from 0.0 to 1.0, below 1.0 only for a stick
+// that is not pushed all the way. Holding the mouse or a touch only adds
+// rotate strength for steering: it never presses an action, so clicking
+// through menus does not also move through them.
+#[derive(Resource, Debug, Default)]
+pub struct ActionState {
+    strength: HashMap<Action, f32>,
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    // whichever rotate direction went down most recently
+    last_rotate: Option<Action>,
+}
+
+// Turns device input into `ActionState` early in every frame.
+#[derive(Default)]
+pub struct ControlsPlugin {
+    // file the bindings are loaded from and saved to; `None` plays with the
+    // defaults, as tests and tools do
+    pub bindings_path: Option<PathBuf>,
+}
+
+// The rebinding screen, entered from the menu.
+pub struct ControlsScreenPlugin;
+
+#[derive(Component)]
+struct OnControlsScreen;
+
+#[derive(Component)]
+struct ControlsItem {
+    index: usize,
+}
+
+#[derive(Resource, Default)]
+struct ControlsMenu {
+    hover: usize,
+    // the action waiting for a key or button to be bound to it
+    listening: Option<Action>,
+}
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        let bindings = match self.bindings_path.as_ref() {
+            Some(path) => Bindings::load(path.clone()),
+            None => Bindings::default(),
+        };
+        app.insert_resource(bindings)
+            .init_resource::<ActionState>()
+            .add_systems(PreUpdate, controls_update.after(InputSystem));
+    }
+}
+
+impl Plugin for ControlsScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ControlsMenu>()
+            .add_systems(OnEnter(GameState::Controls), controls_setup)
+            .add_systems(
+                Update,
+                (
+                    controls_input,
+                    controls_update_text.run_if(
+                        resource_changed::<ControlsMenu>.or_else(resource_changed::<Bindings>),
+                    ),
+                )
+                    .chain()
+                    .run_if(in_state(GameState::Controls)),
+            )
+            .add_systems(OnExit(GameState::Controls), controls_cleanup);
+    }
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn any_just_pressed(&self, actions: impl IntoIterator<Item = Action>) -> bool {
+        actions.into_iter().any(|action| self.just_pressed(action))
+    }
+
+    pub fn strength(&self, action: Action) -> f32 {
+        self.strength.get(&action).copied().unwrap_or_default()
+    }
+
+    // Rotation from -1.0, fully left, to 1.0, fully right, with both
+    // directions held resolved by `both_pressed`.
+    pub fn steer(&self, both_pressed: BothPressed) -> f32 {
+        let left = self.strength(Action::RotateLeft);
+        let right = self.strength(Action::RotateRight);
+        if left == 0.0 || right == 0.0 {
+            return right - left;
+        }
+        let last = self.last_rotate.unwrap_or(Action::RotateRight);
+        let pick = match both_pressed {
+            BothPressed::LastPressed => last,
+            BothPressed::FirstPressed if last == Action::RotateLeft => Action::RotateRight,
+            BothPressed::FirstPressed => Action::RotateLeft,
+            BothPressed::Cancel => return 0.0,
+        };
+        if pick == Action::RotateLeft {
+            -left
+        } else {
+            right
+        }
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Action::*;
+        use GamepadButtonType::*;
+        Bindings {
+            version: CONTROLS_VERSION,
+            keys: BTreeMap::from([
+                (RotateLeft, vec![KeyCode::ArrowLeft, KeyCode::KeyA]),
+                (RotateRight, vec![KeyCode::ArrowRight, KeyCode::KeyD]),
+                (Up, vec![KeyCode::ArrowUp, KeyCode::KeyW]),
+                (Down, vec![KeyCode::ArrowDown, KeyCode::KeyS]),
+                (Confirm, vec![KeyCode::Enter, KeyCode::Space]),
+                (Back, vec![KeyCode::Backspace]),
+                (Pause, vec![KeyCode::Escape, KeyCode::KeyP]),
+            ]),
+            buttons: BTreeMap::from([
+                (RotateLeft, vec![DPadLeft, LeftTrigger]),
+                (RotateRight, vec![DPadRight, RightTrigger]),
+                (Up, vec![DPadUp]),
+                (Down, vec![DPadDown]),
+                (Confirm, vec![South]),
+                (Back, vec![East]),
+                (Pause, vec![Start]),
+            ]),
+            both_pressed: BothPressed::default(),
+            path: None,
+        }
+    }
+}
+
+impl Bindings {
+    // A missing, unreadable or outdated file falls back to the defaults,
+    // like the high scores do.
+    pub fn load(path: PathBuf) -> Bindings {
+        let loaded = match fs::read_to_string(&path) {
+            Ok(contents) => match ron::from_str::<Bindings>(&contents) {
+                Ok(bindings) if bindings.version == CONTROLS_VERSION => Some(bindings),
+                Ok(bindings) => {
+                    warn!(
+                        "ignoring controls at {} with unknown version {}",
+                        path.display(),
+                        bindings.version
+                    );
+                    None
+                }
+                Err(err) => {
+                    warn!("ignoring corrupt controls at {}: {}", path.display(), err);
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+        Bindings {
+            path: Some(path),
+            ..loaded.unwrap_or_default()
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(path) = self.path.as_ref() else {
+            return;
+        };
+        if let Err(err) = self.write(path) {
+            warn!("could not save controls to {}: {}", path.display(), err);
+        }
+    }
+
+    fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.keys.get(&action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn buttons(&self, action: Action) -> &[GamepadButtonType] {
+        self.buttons.get(&action).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    // Rebinding replaces every key of the action, so the old one stops
+    // working. Returns the actions the key was taken from.
+    pub fn bind_key(&mut self, action: Action, key: KeyCode) -> Vec<Action> {
+        rebind(&mut self.keys, action, key)
+    }
+
+    pub fn bind_button(&mut self, action: Action, button: GamepadButtonType) -> Vec<Action> {
+        rebind(&mut self.buttons, action, button)
+    }
+
+    pub fn cycle_both_pressed(&mut self) {
+        self.both_pressed = match self.both_pressed {
+            BothPressed::LastPressed => BothPressed::FirstPressed,
+            BothPressed::FirstPressed => BothPressed::Cancel,
+            BothPressed::Cancel => BothPressed::LastPressed,
+        };
+    }
+
+    pub fn reset(&mut self) {
+        *self = Bindings {
+            path: self.path.take(),
+            ..default()
+        };
+    }
+}
+
+// Binds `input` to `action` alone. Another action it was bound to loses it,
+// and if that leaves the other action unbound, it takes the ones `action`
+// had, so the two swap.
+fn rebind<T: Copy + PartialEq>(
+    bound: &mut BTreeMap<Action, Vec<T>>,
+    action: Action,
+    input: T,
+) -> Vec<Action> {
+    let replaced = bound.insert(action, vec![input]).unwrap_or_default();
+    let mut taken = Vec::new();
+    for (other, inputs) in bound.iter_mut() {
+        if *other == action || !inputs.contains(&input) {
+            continue;
+        }
+        inputs.retain(|bound| *bound != input);
+        if inputs.is_empty() {
+            inputs.extend(replaced.iter().filter(|bound| **bound != input));
+        }
+        taken.push(*other);
+    }
+    taken
+}
+
+#[allow(clippy::too_many_arguments)]
+fn controls_update(
+    bindings: Res<Bindings>,
+    keys: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut state: ResMut<ActionState>,
+) {
+    let mut strength: HashMap<Action, f32> = ACTIONS
+        .into_iter()
+        .filter(|action| {
+            keys.any_pressed(bindings.keys(*action).iter().copied())
+                || gamepads.iter().any(|gamepad| {
+                    bindings
+                        .buttons(*action)
+                        .iter()
+                        .any(|button| buttons.pressed(GamepadButton::new(gamepad, *button)))
+                })
+        })
+        .map(|action| (action, 1.0))
+        .collect();
+    let mut hold = |action, value: f32| {
+        let held = strength.entry(action).or_default();
+        *held = held.max(value);
+    };
+
+    // Sticks count from the edge of their deadzone, so a slight push
+    // rotates slowly.
+    for gamepad in gamepads.iter() {
+        let stick = |axis| {
+            let value: f32 = axes
+                .get(GamepadAxis::new(gamepad, axis))
+                .unwrap_or_default();
+            let push = ((value.abs() - STICK_DEADZONE) / (1.0 - STICK_DEADZONE)).clamp(0.0, 1.0);
+            (value.signum(), push)
+        };
+        let (x, push) = stick(GamepadAxisType::LeftStickX);
+        if push > 0.0 {
+            hold(if x < 0.0 { Action::RotateLeft } else { Action::RotateRight }, push);
+        }
+        let (y, push) = stick(GamepadAxisType::LeftStickY);
+        if push > 0.0 {
+            hold(if y > 0.0 { Action::Up } else { Action::Down }, push);
+        }
+    }
+
+    let pressed: HashSet<Action> = strength
+        .iter()
+        .filter(|(_, value)| **value >= STICK_THRESHOLD)
+        .map(|(action, _)| *action)
+        .collect();
+
+    // Holding the left or right half of the window rotates that way.
+    if let Ok(window) = windows.get_single() {
+        let cursor = window
+            .cursor_position()
+            .filter(|_| mouse.pressed(MouseButton::Left));
+        for position in touches.iter().map(|touch| touch.position()).chain(cursor) {
+            let action = if position.x < window.width() / 2.0 {
+                Action::RotateLeft
+            } else {
+                Action::RotateRight
+            };
+            strength.insert(action, 1.0);
+        }
+    }
+
+    strength.retain(|_, value| *value > 0.0);
+    for action in [Action::RotateLeft, Action::RotateRight] {
+        if strength.contains_key(&action) && !state.strength.contains_key(&action) {
+            state.last_rotate = Some(action);
+        }
+    }
+
+    state.just_pressed = pressed.difference(&state.pressed).copied().collect();
+    state.pressed = pressed;
+    state.strength = strength;
+}
+
+fn controls_label(bindings: &Bindings, action: Action) -> String {
+    let keys: Vec<String> = bindings
+        .keys(action)
+        .iter()
+        .map(|key| format!("{:?}", key))
+        .collect();
+    let buttons: Vec<String> = bindings
+        .buttons(action)
+        .iter()
+        .map(|button| format!("{:?}", button))
+        .collect();
+    format!(
+        "{:?}: {} / {}",
+        action,
+        keys.join(", "),
+        buttons.join(", ")
+    )
+}
+
+fn controls_setup(mut commands: Commands, mut menu: ResMut<ControlsMenu>) {
+    *menu = ControlsMenu::default();
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: Color::BLACK.into(),
+                ..default()
+            },
+            OnControlsScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    "Controls",
+                    TextStyle {
+                        font_size: 80.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::all(Val::Px(30.0)),
+                    ..default()
+                }),
+            );
+
+            // one item per action, then the both-pressed policy and "Reset to
+            // defaults"
+            for index in 0..CONTROLS_ITEMS {
+                parent.spawn((
+                    TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font_size: 32.0,
+                            color: CONTROLS_ITEM_COLOR,
+                            ..default()
+                        },
+                    )
+                    .with_style(Style {
+                        margin: UiRect::all(Val::Px(6.0)),
+                        ..default()
+                    }),
+                    ControlsItem { index },
+                ));
+            }
+
+            parent.spawn(
+                TextBundle::from_section(
+                    "Confirm to rebind, then press a key or button. Back or Pause cancels.",
+                    TextStyle {
+                        font_size: 24.0,
+                        color: Color::GRAY,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::top(Val::Px(30.0)),
+                    ..default()
+                }),
+            );
+        });
+}
+
+fn controls_input(
+    actions: Res<ActionState>,
+    keys: Res<ButtonInput<KeyCode>>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    mut menu: ResMut<ControlsMenu>,
+    mut bindings: ResMut<Bindings>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    // Anything bound to Back or Pause cancels, so using one of their keys or
+    // buttons for another action takes rebinding Back or Pause away from it.
+    if let Some(action) = menu.listening {
+        if actions.any_just_pressed([Action::Back, Action::Pause]) {
+            menu.listening = None;
+        } else if let Some(key) = keys.get_just_pressed().next() {
+            info!("binding {:?} to {:?}", action, key);
+            for other in bindings.bind_key(action, *key) {
+                warn!("{:?} was bound to {:?} too, moved it to {:?}", key, other, action);
+            }
+            bindings.save();
+            menu.listening = None;
+        } else if let Some(button) = buttons.get_just_pressed().next() {
+            info!("binding {:?} to {:?}", action, button.button_type);
+            for other in bindings.bind_button(action, button.button_type) {
+                warn!(
+                    "{:?} was bound to {:?} too, moved it to {:?}",
+                    button.button_type, other, action
+                );
+            }
+            bindings.save();
+            menu.listening = None;
+        }
+        return;
+    }
+
+    let items = CONTROLS_ITEMS;
+    if actions.any_just_pressed([Action::Back, Action::Pause]) {
+        game_state.set(GameState::Menu);
+    }
+    if actions.just_pressed(Action::Up) {
+        menu.hover = (menu.hover + items - 1) % items;
+    }
+    if actions.just_pressed(Action::Down) {
+        menu.hover = (menu.hover + 1) % items;
+    }
+    if actions.just_pressed(Action::Confirm) {
+        match ACTIONS.get(menu.hover) {
+            Some(action) => menu.listening = Some(*action),
+            None if menu.hover == ACTIONS.len() => {
+                bindings.cycle_both_pressed();
+                bindings.save();
+            }
+            None => {
+                info!("resetting controls");
+                bindings.reset();
+                bindings.save();
+            }
+        }
+    }
+}
+
+fn controls_update_text(
+    menu: Res<ControlsMenu>,
+    bindings: Res<Bindings>,
+    mut query: Query<(&mut Text, &ControlsItem)>,
+) {
+    for (mut text, item) in query.iter_mut() {
+        let (value, color) = match ACTIONS.get(item.index) {
+            Some(action) if menu.listening == Some(*action) => (
+                format!("{:?}: press a key or button", action),
+                CONTROLS_LISTEN_COLOR,
+            ),
+            Some(action) => (controls_label(&bindings, *action), CONTROLS_ITEM_COLOR),
+            None if item.index == ACTIONS.len() => (
+                format!("Both directions held: {:?}", bindings.both_pressed),
+                CONTROLS_ITEM_COLOR,
+            ),
+            None => ("Reset to defaults".to_string(), CONTROLS_ITEM_COLOR),
+        };
+        let color = if item.index == menu.hover && menu.listening.is_none() {
+            CONTROLS_HOVER_COLOR
+        } else {
+            color
+        };
+        text.sections[0].value = value;
+        text.sections[0].style.color = color;
+    }
+}
+
+fn controls_cleanup(query: Query<Entity, With<OnControlsScreen>>, mut commands: Commands) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,181 @@
+use crate::game::{ActiveRun, OnGameScreen};
+use crate::patterns::PatternLibrary;
+use crate::seed::GameRng;
+use crate::sim::{angle_in_sector, Rotation, Wall, PLAYER_HIT_ARC, PLAYER_HIT_HEIGHT};
+use crate::Game;
+use bevy::diagnostic::{DiagnosticsStore, FrameTimeDiagnosticsPlugin};
+use bevy::prelude::*;
+
+const DEBUG_TOGGLE_KEY: KeyCode = KeyCode::F3;
+const DEBUG_FONT_SIZE: f32 = 18.0;
+const RING_COLOR: Color = Color::CYAN;
+const SIDE_COLOR: Color = Color::GRAY;
+const WALL_COLOR: Color = Color::RED;
+const NEAR_WALL_COLOR: Color = Color::YELLOW;   // walls across the player's angle
+const HITBOX_COLOR: Color = Color::GREEN;
+
+// Whether the debug overlay is shown. Stays as it is from one run to the next.
+#[derive(Resource, Debug, Default)]
+pub struct DebugOverlay {
+    pub visible: bool,
+}
+
+#[derive(Component)]
+struct DebugPanel;
+
+// Raw state of the run for working on the game, off until toggled with F3: a
+// text panel, and gizmos tracing the shapes `game_collision` tests, at the
+// last tick rather than interpolated like the sprites. It stays up for as
+// long as the run's arena does, pauses included.
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<FrameTimeDiagnosticsPlugin>() {
+            app.add_plugins(FrameTimeDiagnosticsPlugin);
+        }
+        app.init_resource::<DebugOverlay>().add_systems(
+            Update,
+            (
+                debug_toggle,
+                debug_setup.run_if(resource_added::<ActiveRun>),
+                (debug_panel, debug_gizmos.run_if(debug_visible))
+                    .run_if(resource_exists::<ActiveRun>),
+            )
+                .chain(),
+        );
+    }
+}
+
+fn debug_visible(overlay: Res<DebugOverlay>) -> bool {
+    overlay.visible
+}
+
+fn debug_toggle(keys: Res<ButtonInput<KeyCode>>, mut overlay: ResMut<DebugOverlay>) {
+    if keys.just_pressed(DEBUG_TOGGLE_KEY) {
+        overlay.visible = !overlay.visible;
+    }
+}
+
+fn debug_setup(mut commands: Commands) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: DEBUG_FONT_SIZE,
+                color: Color::GREEN,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(10.0),
+            right: Val::Px(10.0),
+            ..default()
+        })
+        .with_background_color(Color::rgba(0.0, 0.0, 0.0, 0.6)),
+        DebugPanel,
+        OnGameScreen,
+    ));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn debug_panel(
+    game: Res<Game>,
+    overlay: Res<DebugOverlay>,
+    diagnostics: Res<DiagnosticsStore>,
+    rotation: Res<Rotation>,
+    rng: Res<GameRng>,
+    library: Res<PatternLibrary>,
+    walls: Query<(), With<Wall>>,
+    mut query: Query<(&mut Text, &mut Visibility), With<DebugPanel>>,
+) {
+    for (mut text, mut visibility) in query.iter_mut() {
+        *visibility = if overlay.visible {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if !overlay.visible {
+            continue;
+        }
+
+        let fps = diagnostics
+            .get(&FrameTimeDiagnosticsPlugin::FPS)
+            .and_then(|fps| fps.smoothed())
+            .unwrap_or(0.0);
+        let pattern = match game.pattern {
+            Some(index) => format!(
+                "{} ({})",
+                index,
+                library.patterns().get(index).map_or("?", |pattern| &pattern.name)
+            ),
+            None => "-".to_string(),
+        };
+        text.sections[0].value = format!(
+            "fps: {:.0}\nwalls: {}\ntheta: {:.2}\nrotation: {:.1} deg/s\npattern: {}\nseed: {}\n\
+             player: {:?}\nscore: {:.2}\nplayer_ring: {:.1}\ncenter_ring: {:.1}\nwall_ring: {:.1}",
+            fps,
+            walls.iter().count(),
+            game.theta,
+            rotation.speed,
+            pattern,
+            rng.seed,
+            game.player,
+            game.score,
+            game.player_radius,
+            game.center_ring_radius,
+            game.wall_ring_radius
+        );
+    }
+}
+
+fn polar(angle: f32, radius: f32) -> Vec2 {
+    Vec2::from_angle(angle.to_radians()) * radius
+}
+
+fn debug_gizmos(game: Res<Game>, walls: Query<&Wall>, mut gizmos: Gizmos) {
+    let arena = &game.arena;
+
+    for radius in [game.center_ring_radius, game.player_radius, game.wall_ring_radius] {
+        gizmos.circle_2d(Vec2::ZERO, radius, RING_COLOR).segments(64);
+    }
+    for side in 0..arena.side_count() {
+        let (start, _) = arena.sector(game.theta, side);
+        gizmos.line_2d(Vec2::ZERO, polar(start, game.wall_ring_radius), SIDE_COLOR);
+    }
+
+    // The band each wall collides with, lit when it lies across the player.
+    for wall in walls.iter() {
+        let (start, width) = arena.sector(game.theta, wall.side);
+        if width <= 0.0 {
+            continue;
+        }
+        let color = if angle_in_sector(game.player.theta, start, width) {
+            NEAR_WALL_COLOR
+        } else {
+            WALL_COLOR
+        };
+        let band = arena.band(
+            game.theta,
+            wall.side,
+            wall.distance - wall.thickness / 2.0,
+            wall.distance + wall.thickness / 2.0,
+        );
+        gizmos.linestrip_2d(band.into_iter().chain([band[0]]), color);
+    }
+
+    let (theta, radius) = (game.player.theta, game.player_radius);
+    let (left, right) = (theta - PLAYER_HIT_ARC, theta + PLAYER_HIT_ARC);
+    let (inner, outer) = (radius - PLAYER_HIT_HEIGHT, radius + PLAYER_HIT_HEIGHT);
+    gizmos.linestrip_2d(
+        [
+            polar(left, inner),
+            polar(right, inner),
+            polar(right, outer),
+            polar(left, outer),
+            polar(left, inner),
+        ],
+        HITBOX_COLOR,
+    );
+}
@@ -1,665 +1,507 @@
+use crate::controls::{ActionState, Bindings};
+use crate::levels::{CurrentLevel, LevelLibrary, SelectedLevel, Speeds};
+use crate::music::BeatClock;
+use crate::patterns::PatternLibrary;
+use crate::seed::{GameRng, SeedMode};
+use crate::settings::Settings;
+use crate::sim::{
+    pattern_spawn_delay, pattern_walls, sweep_player, Arena, Rotation, RotationEvent, Wall,
+    BEAT_PULSE_WIDTH, CENTER_HEX_RADIUS, PLAYER_RING_RADIUS, WALL_RING_RADIUS,
+};
 use crate::{Game, GameState};
 use bevy::prelude::*;
-use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
 use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
-const WALL_SIDES: u32 = 6u32;
-const PLAYER_MOVE_SPEED: f32 = 5.0;
-const INITIAL_RING_RADIUS: f32 = 100.0;
-const WALL_SPIN_SPEED: f32 = 1.0;
-const WALL_SHRINK_SPEED: f32 = 0.01;
-const WALL_RING_RADIUS: f32 = 600.0;
-const WALL_HEIGHT: f32 = 10.0;
-const CENTER_HEX_RADIUS: f32 = 100.0;
-const CENTER_HEX_HEIGHT: f32 = 10.0;
-const PLAYER_RING_RADIUS: f32 = CENTER_HEX_RADIUS + 60.0;
-const BORDER_WIDTH: f32 = 3000.0;
-const BORDER_HEIGHT: f32 = 10.0;
-const BACKGROUD_MOVE_SPEED: f32 = 0.5;
-
-const WAVE_WIDTH: f32 = 50.0;
+const DEFAULT_TICK_RATE: f64 = 60.0;
 
 // /-\
 // \-/
 
 #[derive(Component)]
-struct OnGameScreen;
+pub struct OnGameScreen;
 
+// Present while a run is in progress, including while it is paused.
 #[derive(Resource)]
-struct GameGlobalTimer(Timer);
+pub struct ActiveRun;
 
 #[derive(Resource)]
-struct GameMoveTimer(Timer);
+struct GameGlobalTimer(Timer);
 
 #[derive(Resource)]
-struct GamePlayerTrackerTimer(Timer);
+struct WallSpawnTimer(Timer);
 
+// Ends the run as survived, for levels with a duration.
 #[derive(Resource)]
-struct GamePlayerInputTimer(Timer);
-
-#[derive(Component)]
-struct PlayerSprite;
-
-#[derive(Component)]
-struct PlayerTracker;
-
-#[derive(Component)]
-struct BackgroundSlice {
-    offset_theta: f32,
-    index: u32,
-}
+struct ScoreTimer(Option<Timer>);
 
 #[derive(Resource)]
-struct BorderMoveTimer(Timer);
+struct MorphTimer {
+    timer: Timer,
+    next: usize,
+}
 
-#[derive(Resource)]
-struct BackgroundMoveTimer(Timer);
+// Simulation values from the previous fixed tick, so rendering can
+// interpolate between ticks instead of snapping at the tick rate.
+#[derive(Resource, Default)]
+pub struct PreviousTick {
+    pub theta: f32,
+    pub arena: Arena,
+    pub player_theta: f32,
+    pub player_radius: f32,
+    pub center_ring_radius: f32,
+}
 
-#[derive(Resource)]
-struct WallSpawnTimer(Timer);
+// The player's input for the current tick, as consumed by
+// `game_handle_input`: how hard they steer, in hundredths from -100, fully
+// left, to 100, fully right. Read from the controls in `TickSet::Input`;
+// replays overwrite it there too.
+#[derive(Resource, Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerInput(i8);
 
-#[derive(Resource)]
-struct WallMoveTimer(Timer);
+impl PlayerInput {
+    pub fn new(steer: f32) -> PlayerInput {
+        PlayerInput((steer.clamp(-1.0, 1.0) * 100.0).round() as i8)
+    }
 
-#[derive(Resource)]
-struct CenterHexMoveTimer(Timer);
+    pub fn steer(&self) -> f32 {
+        self.0 as f32 / 100.0
+    }
+}
 
-#[derive(Resource)]
-struct GameRotateTimer(Timer);
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TickSet {
+    Input,
+    Simulate,
+}
 
-#[derive(Resource)]
-struct ScoreTimer(Timer);
+// Throws the current run away and starts a fresh one without leaving
+// `GameState::Playing`.
+#[derive(Event)]
+pub struct RestartRun;
 
-#[derive(Resource)]
-struct RadiusShrinkerTimer(Timer);
+// Sent on the tick a wall hits the player, ending the run.
+#[derive(Event, Debug, Clone)]
+pub struct PlayerHit {
+    pub pattern: String,
+}
 
-#[derive(Component)]
-struct Wall {
-    index: u32,
-    ring_radius: f32,
-    posn: f32,              // 0.0 to 1.0 where 0.0 is the center hex
+// Sent on the tick a run reaches one of its level's milestones.
+#[derive(Event, Debug, Clone)]
+pub struct MilestoneReached {
+    pub name: String,
 }
 
-#[derive(Component)]
-struct CenterHex {
-    offset_theta: f32,
-    index: u32,
+// Sent on the tick the music reaches a beat, counting from 1.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct BeatReached {
+    pub beat: u32,
 }
 
-#[derive(Component)]
-struct Borders {
-    offset_theta: f32,
-    index: u32,
+pub struct GamePlugin {
+    // simulation ticks per second
+    pub tick_rate: f64,
 }
 
-pub struct GamePlugin;
+impl Default for GamePlugin {
+    fn default() -> Self {
+        GamePlugin {
+            tick_rate: DEFAULT_TICK_RATE,
+        }
+    }
+}
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
+        app.insert_resource(Time::<Fixed>::from_hz(self.tick_rate))
+            .init_resource::<PreviousTick>()
+            .init_resource::<PatternLibrary>()
+            .init_resource::<PlayerInput>()
+            .init_resource::<LevelLibrary>()
+            .init_resource::<SelectedLevel>()
+            .init_resource::<CurrentLevel>()
+            .add_event::<RestartRun>()
+            .add_event::<MilestoneReached>()
+            .add_event::<BeatReached>()
+            .add_event::<PlayerHit>()
+            .add_systems(
             OnEnter(GameState::Playing),
-            (
-                game_setup,
-                spawn_background_slices,
-                spawn_background_borders,
-                spawn_center_hex,
-            )
-                .chain(),
+            (game_cleanup, game_setup)
+                .chain()
+                .run_if(not(resource_exists::<ActiveRun>)),
         )
         .add_systems(
             Update,
+            (game_cleanup, game_setup)
+                .chain()
+                .run_if(in_state(GameState::Playing).and_then(on_event::<RestartRun>())),
+        )
+        .configure_sets(
+            FixedUpdate,
+            (TickSet::Input, TickSet::Simulate)
+                .chain()
+                .run_if(in_state(GameState::Playing).and_then(run_in_progress)),
+        )
+        .add_systems(FixedUpdate, game_read_input.in_set(TickSet::Input))
+        .add_systems(
+            FixedUpdate,
             (
+                game_snapshot,
                 game,
-                game_player_tracker,
                 game_handle_input,
                 game_wallspawner,
                 game_wallmover,
-                game_background_mover,
-                game_border_mover,
-                game_center_hex_mover,
                 game_theta_mover,
+                game_arena_morph,
+                game_beat_pulse,
                 game_collision,
                 game_score,
-                game_radius_shrinker,
             )
                 .chain()
-                .run_if(in_state(GameState::Playing)),
+                .in_set(TickSet::Simulate),
+        )
+        // The arena of a lost run stays in view behind the game over screen,
+        // where the death shakes it.
+        .add_systems(
+            OnExit(GameState::Playing),
+            game_cleanup.run_if(not(
+                in_state(GameState::Paused).or_else(in_state(GameState::GameOver)),
+            )),
+        )
+        .add_systems(
+            OnExit(GameState::Paused),
+            game_cleanup.run_if(not(in_state(GameState::Playing))),
         )
-        .add_systems(OnExit(GameState::Playing), game_cleanup);
+        .add_systems(OnExit(GameState::GameOver), game_cleanup);
     }
 }
 
-const wall_patterns: [[i32; 3]; 10] = [
-    [0, 2, 4],
-    [1, 3, 5],
-    [0, 1, 2],
-    [3, 4, 5],
-    [0, 0, 0],
-    [1, 1, 1],
-    [2, 2, 2],
-    [3, 3, 3],
-    [4, 4, 4],
-    [5, 5, 5]
-];
+// The state only changes between frames, so a slow frame with several
+// ticks would otherwise keep playing a run that has already ended.
+pub fn run_in_progress(game: Res<Game>) -> bool {
+    !game.over
+}
 
 fn game_score(
-    mut commands: Commands,
     mut score_timer: ResMut<ScoreTimer>,
     mut game_state: ResMut<NextState<GameState>>,
     time: Res<Time>,
+    level: Res<CurrentLevel>,
+    mut speeds: ResMut<Speeds>,
+    mut milestones: EventWriter<MilestoneReached>,
     mut game: ResMut<Game>,
 ) {
-    if score_timer.0.tick(time.delta()).just_finished() {
-        game_state.set(GameState::Menu);
+    if let Some(timer) = score_timer.0.as_mut() {
+        if timer.tick(time.delta()).just_finished() {
+            game.over = true;
+            game_state.set(GameState::GameOver);
+        }
     }
 
     game.score += time.delta().as_secs_f32();
-}
 
+    let milestone = level
+        .0
+        .milestone(game.score)
+        .map(|milestone| &milestone.name);
+    if milestone != game.milestone.as_ref() {
+        info!("reached milestone {:?}", milestone);
+        game.milestone = milestone.cloned();
+        *speeds = level.0.speeds(game.score);
+        if let Some(name) = milestone {
+            milestones.send(MilestoneReached { name: name.clone() });
+        }
+    }
+}
 
+#[allow(clippy::too_many_arguments)]
 fn game_wallspawner(
     mut commands: Commands,
-    mut game: ResMut<Game>,
     mut timer: ResMut<WallSpawnTimer>,
     time: Res<Time>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    asset_server: Res<AssetServer>,
+    library: Res<PatternLibrary>,
+    level: Res<CurrentLevel>,
+    speeds: Res<Speeds>,
+    mut game: ResMut<Game>,
+    mut rotation: ResMut<Rotation>,
+    mut rng: ResMut<GameRng>,
 ) {
     if !timer.0.tick(time.delta()).finished() {
         return;
     }
 
-    let mut rng = rand::thread_rng();
-    let pattern_number: f32 = (
-        rng.gen::<f32>() * wall_patterns.len() as f32
-    ) % wall_patterns.len() as f32;
-
-
-    let pattern = wall_patterns[pattern_number as usize].clone();
-    for side in pattern.iter() {
-        let wallside = *side as f32;
-        let theta = game.theta;
-        let theta = (theta + wallside as f32 * 60.0) % 360.0;
-        let theta = (theta + 30.0) % 360.0;
-        let x = theta.to_radians().cos() * WALL_RING_RADIUS;
-        let y = theta.to_radians().sin() * WALL_RING_RADIUS;
-        let translation = Vec3::new(x, y, 10.0);
-        let x1 = (1.0 * WALL_RING_RADIUS);
-        let x2 = (60.0_f32.to_radians().cos() * WALL_RING_RADIUS);
-        let y1 = 0.0;
-        let y2 = (60.0_f32.to_radians().sin() * WALL_RING_RADIUS);
-        let scale_x = ((x1 - x2).powi(2) + (y1 - y2).powi(2)).sqrt() + (WALL_RING_RADIUS / 6.25);
-        let scale = Vec3::new(scale_x, WALL_HEIGHT, 1.0);
-        let rotation = Quat::from_rotation_z(theta.to_radians() + 90.0_f32.to_radians());
-
-        commands.spawn((
-            SpriteBundle {
-                transform: Transform {
-                    translation: translation,
-                    scale: scale,
-                    rotation: rotation,
-                },
-                sprite: Sprite {
-                    color: Color::WHITE,
-                    ..default()
-                },
-                ..default()
-            },
-            OnGameScreen,
-            Wall {
-                index: side.clone() as u32,
-                ring_radius: WALL_RING_RADIUS,
-                posn: 1.0,
-            },
-        ));
+    // A level whose patterns are all missing plays with every pattern rather
+    // than none.
+    let all = library.patterns();
+    let mut patterns: Vec<usize> = (0..all.len())
+        .filter(|&index| level.0.allows(&all[index]))
+        .collect();
+    if patterns.is_empty() {
+        patterns = (0..all.len()).collect();
     }
+    let index = patterns[rng.rng.gen_range(0..patterns.len())];
+    let pattern = &all[index];
+    game.pattern = Some(index);
+
+    // While the arena morphs, a gap on a side that is shrinking away would
+    // close, so patterns are laid out for the side count it is heading to.
+    for wall in pattern_walls(pattern, game.arena.target_sides) {
+        commands.spawn((OnGameScreen, wall));
+    }
+    if let Some(event) = pattern.rotation {
+        info!("{} requests rotation {:?}", pattern.name, event);
+        rotation.request(event, &level.0.rotation);
+    }
+
+    timer
+        .0
+        .set_duration(Duration::from_secs_f32(pattern_spawn_delay(
+            pattern,
+            speeds.spawn_interval,
+            speeds.wall_speed,
+        )));
 }
 
 fn game_collision(
-    mut commands: Commands,
-    game: Res<Game>,
+    mut game: ResMut<Game>,
     mut game_state: ResMut<NextState<GameState>>,
-    mut query: Query<(&Transform, &Wall)>,
-    mut player_query: Query<(&Transform, &PlayerSprite)>,
+    mut hits: EventWriter<PlayerHit>,
+    query: Query<&Wall>,
 ) {
-    for (player_transform, _) in player_query.iter() {
-        for (wall_transform, wall) in query.iter() {
-            let player_x = player_transform.translation.x;
-            let player_y = player_transform.translation.y;
-            let wall_x = wall_transform.translation.x;
-            let wall_y = wall_transform.translation.y;
-            let distance = ((player_x - wall_x).powi(2) + (player_y - wall_y).powi(2)).sqrt();
-            if distance < 25.0 {
-                game_state.set(GameState::Menu);
-            }
+    for wall in query.iter() {
+        if wall.hits_player(
+            &game.arena,
+            game.theta,
+            game.player.theta,
+            game.player_radius,
+        ) {
+            info!("player hit wall on side {} of {}", wall.side, wall.pattern);
+            hits.send(PlayerHit {
+                pattern: wall.pattern.clone(),
+            });
+            game.killed_by = Some(wall.pattern.clone());
+            game.over = true;
+            game_state.set(GameState::GameOver);
+            return;
         }
     }
 }
 
-fn game_radius_shrinker(
-    mut commands: Commands,
+// The rings swell on every beat of the level's music and settle back
+// between beats.
+fn game_beat_pulse(
     time: Res<Time>,
+    mut clock: ResMut<BeatClock>,
     mut game: ResMut<Game>,
-    mut timer: ResMut<RadiusShrinkerTimer>
+    mut beats: EventWriter<BeatReached>,
 ) {
-    if !timer.0.tick(time.delta()).finished() {
-        return;
+    let before = clock.beat();
+    clock.advance(time.delta_seconds());
+    if clock.beat() > before {
+        beats.send(BeatReached { beat: clock.beat() });
     }
-
-    game.center_ring_radius = CENTER_HEX_RADIUS + (WAVE_WIDTH * game.theta.to_radians().sin());
-    game.player_radius = PLAYER_RING_RADIUS + (WAVE_WIDTH * game.theta.to_radians().sin());
-    game.wall_ring_radius = WALL_RING_RADIUS + (WAVE_WIDTH * game.theta.to_radians().sin());
+    let pulse = BEAT_PULSE_WIDTH * clock.pulse();
+    game.center_ring_radius = CENTER_HEX_RADIUS + pulse;
+    game.player_radius = PLAYER_RING_RADIUS + pulse;
+    game.wall_ring_radius = WALL_RING_RADIUS + pulse;
 }
 
 fn game_wallmover(
     mut commands: Commands,
-    mut move_timer: ResMut<WallMoveTimer>,
-    mut game: ResMut<Game>,
+    game: Res<Game>,
+    speeds: Res<Speeds>,
     time: Res<Time>,
-    mut query: Query<(&mut Transform, &mut Wall, Entity), With<Wall>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut query: Query<(&mut Wall, Entity)>,
 ) {
-    if !move_timer.0.tick(time.delta()).finished() {
-        return;
-    }
+    for (mut wall, entity) in query.iter_mut() {
+        wall.advance(
+            time.delta_seconds(),
+            game.wall_ring_radius,
+            speeds.wall_speed,
+        );
 
-    for (mut transform, mut wall, entity) in query.iter_mut() {
-        let theta = game.theta;
-        let theta = (theta + wall.index as f32 * 60.0) % 360.0;
-        let theta = (theta + 30.0) % 360.0;
-        transform.rotation = Quat::from_rotation_z(theta.to_radians() + 90.0_f32.to_radians());
-        wall.posn -= 0.01;
-        wall.ring_radius = game.wall_ring_radius * wall.posn;
-        let x = theta.to_radians().cos() * wall.ring_radius;
-        let y = theta.to_radians().sin() * wall.ring_radius;
-        let x1 = (1.0 * wall.ring_radius);
-        let x2 = (60.0_f32.to_radians().cos() * wall.ring_radius);
-        let y1 = 0.0;
-        let y2 = (60.0_f32.to_radians().sin() * wall.ring_radius);
-        let scale_x = ((x1 - x2).powi(2) + (y1 - y2).powi(2)).sqrt();
-        transform.translation.x = x;
-        transform.translation.y = y;
-        transform.scale.x = scale_x + (wall.ring_radius / 6.25);
-
-        if wall.ring_radius < CENTER_HEX_RADIUS {
+        if wall.reached_center() {
             commands.entity(entity).despawn_recursive();
         }
-
     }
 }
 
+// Random reversals and bursts come sooner the faster the stage spins
+// relative to the level. Milestones and patterns may reverse the spin too,
+// so theta wraps both ways.
 fn game_theta_mover(
-    mut commands: Commands,
-    mut move_timer: ResMut<GameRotateTimer>,
     time: Res<Time>,
+    level: Res<CurrentLevel>,
+    speeds: Res<Speeds>,
+    mut rotation: ResMut<Rotation>,
+    mut rng: ResMut<GameRng>,
     mut game: ResMut<Game>,
 ) {
-    if move_timer.0.tick(time.delta()).finished() {
-        game.theta = (game.theta + WALL_SPIN_SPEED) % 360.0;
-    }
-}
-
-fn game_background_mover(
-    mut commands: Commands,
-    mut move_timer: ResMut<BackgroundMoveTimer>,
-    time: Res<Time>,
-    game: Res<Game>,
-    mut query: Query<(&mut Transform, &mut BackgroundSlice, &Mesh2dHandle)>,
-) {
-    if move_timer.0.tick(time.delta()).finished() {
-        for (mut transform, mut slice, mut mesh) in query.iter_mut() {
-            transform.rotation = Quat::from_rotation_z(game.theta.to_radians());
+    let style = &level.0.rotation;
+    if let Some((min, max)) = style.event_interval {
+        rotation.next_event -= time.delta_seconds();
+        if rotation.next_event <= 0.0 {
+            let event = if rng.rng.gen_bool(style.burst_chance.clamp(0.0, 1.0)) {
+                RotationEvent::Burst
+            } else {
+                RotationEvent::Reverse
+            };
+            rotation.request(event, style);
+            let stage = (speeds.spin_speed / level.0.spin_speed).abs().max(1.0);
+            rotation.next_event = rng.rng.gen_range(min.min(max)..=max) / stage;
         }
     }
-}
 
-fn game_border_mover(
-    mut commands: Commands,
-    mut move_timer: ResMut<BorderMoveTimer>,
-    time: Res<Time>,
-    game: Res<Game>,
-    mut query: Query<(&mut Transform, &mut Borders)>,
-) {
-    if move_timer.0.tick(time.delta()).finished() {
-        for (mut transform, mut border) in query.iter_mut() {
-            transform.rotation = Quat::from_rotation_z(game.theta.to_radians() + border.index as f32 * 60.0_f32.to_radians());
-        }
-    }
+    rotation.advance(time.delta_seconds(), speeds.spin_speed, style);
+    game.theta = (game.theta + rotation.speed * time.delta_seconds()).rem_euclid(360.0);
 }
 
-fn spawn_background_slices(
-    mut commands: Commands,
-    game: Res<Game>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    asset_server: Res<AssetServer>,
-) {
-    for i in 0..WALL_SIDES {
-        let translation = Vec3::new(0.0, 0.0, 1.0);
-        let scale = Vec3::new(1.0, 1.0, 1.0);
-        let mut color = Color::WHITE;
-        if i % 2 == 0 {
-            color = Color::OLIVE;
-        } else {
-            color = Color::ORANGE_RED;
-        }
-
-        commands.spawn((
-            MaterialMesh2dBundle {
-                mesh: meshes
-                    .add(Mesh::from(Triangle2d {
-                        vertices: [
-                            Vec2::new(
-                                (i as f32 * 60.0).to_radians().cos() * BORDER_WIDTH,
-                                (i as f32 * 60.0).to_radians().sin() * BORDER_WIDTH,
-                            ),
-                            Vec2::new(
-                                ((i as f32 + 1.0) * 60.0).to_radians().cos() * BORDER_WIDTH,
-                                ((i as f32 + 1.0) * 60.0).to_radians().sin() * BORDER_WIDTH,
-                            ),
-                            Vec2::new(0.0, 0.0),
-                        ],
-                    }))
-                    .into(),
-                material: materials.add(color).into(),
-                transform: Transform {
-                    translation: translation,
-                    scale: scale,
-                    ..default()
-                },
-                ..default()
-            },
-            OnGameScreen,
-            BackgroundSlice { offset_theta: 0.0, index: i },
-        ));
-    }
-}
-
-fn spawn_background_borders(
-    mut commands: Commands,
-    game: Res<Game>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    asset_server: Res<AssetServer>,
-) {
-    for i in 0..WALL_SIDES {
-        let scale = Vec3::new(BORDER_WIDTH, BORDER_HEIGHT, 2.0);
-        let theta = (i as f32 * (360.0 / WALL_SIDES as f32));
-        let translation = Vec3::new(0.0, 0.0, 2.0);
-        let rotation = Quat::from_rotation_z(theta.to_radians());
-
-        commands.spawn((
-            SpriteBundle {
-                transform: Transform {
-                    translation: translation,
-                    scale: scale,
-                    rotation: rotation,
-                    ..default()
-                },
-                sprite: Sprite {
-                    color: Color::WHITE,
-                    ..default()
-                },
-                ..default()
-            },
-            OnGameScreen,
-            Borders { offset_theta: theta, index: i },
-        ));
-    }
-}
-
-fn game_center_hex_mover(
-    mut commands: Commands,
-    mut move_timer: ResMut<CenterHexMoveTimer>,
-    game: Res<Game>,
+fn game_arena_morph(
     time: Res<Time>,
-    mut query: Query<(&mut Transform, &mut CenterHex)>,
+    level: Res<CurrentLevel>,
+    mut morph_timer: ResMut<MorphTimer>,
+    mut game: ResMut<Game>,
 ) {
-    if move_timer.0.tick(time.delta()).finished() {
-        for (mut transform, mut center_hex) in query.iter_mut() {
-            let theta = game.theta;
-            let theta = (theta + center_hex.index as f32 * 60.0) % 360.0;
-            let theta = (theta + center_hex.offset_theta) % 360.0;
-            transform.rotation = Quat::from_rotation_z(theta.to_radians() + 90.0_f32.to_radians());
-            let x = theta.to_radians().cos() * game.center_ring_radius;
-            let y = theta.to_radians().sin() * game.center_ring_radius;
-
-            transform.translation.x = x;
-            transform.translation.y = y;
-
-            let scale = Vec3::new(
-                game.center_ring_radius + (game.center_ring_radius / 6.25),
-                CENTER_HEX_HEIGHT,
-                1.0,
-            );
-            transform.scale = scale;
-        }
+    let morph_sides = &level.0.morph_sides;
+    if !morph_sides.is_empty() && morph_timer.timer.tick(time.delta()).just_finished() {
+        let sides = morph_sides[morph_timer.next % morph_sides.len()];
+        morph_timer.next += 1;
+        info!("arena morphing to {} sides", sides);
+        game.arena.morph_to(sides);
     }
-}
-
 
-fn spawn_center_hex(
-    mut commands: Commands,
-    game: Res<Game>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    asset_server: Res<AssetServer>,
-) {
-    for i in 0..WALL_SIDES {
-        let theta = (i as f32 * (360.0 / WALL_SIDES as f32)) + 30.0;
-        let translation = Vec3::new(
-            theta.to_radians().cos() * CENTER_HEX_RADIUS,
-            theta.to_radians().sin() * CENTER_HEX_RADIUS,
-            2.0,
-        );
-        let scale = Vec3::new(
-            CENTER_HEX_RADIUS + (CENTER_HEX_RADIUS / 6.25),
-            CENTER_HEX_HEIGHT,
-            1.0,
-        );
-        let rotation = Quat::from_rotation_z(theta.to_radians() + 90.0_f32.to_radians());
-
-        commands.spawn((
-            SpriteBundle {
-                transform: Transform {
-                    translation: translation,
-                    scale: scale,
-                    rotation: rotation,
-                    ..default()
-                },
-                sprite: Sprite {
-                    color: Color::WHITE,
-                    ..default()
-                },
-                ..default()
-            },
-            OnGameScreen,
-            CenterHex { offset_theta: 30.0, index: i },
-        ));
-    }
+    game.arena.advance(time.delta_seconds());
 }
 
 fn game_setup(
     mut commands: Commands,
-    game: Res<Game>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    asset_server: Res<AssetServer>,
+    mut game: ResMut<Game>,
+    seed_mode: Res<SeedMode>,
+    levels: Res<LevelLibrary>,
+    selected_level: Res<SelectedLevel>,
+    settings: Option<Res<Settings>>,
+    mut current_level: ResMut<CurrentLevel>,
 ) {
-    commands
-        .spawn((
-            NodeBundle {
-                style: Style {
-                    width: Val::Percent(100.0),
-                    height: Val::Percent(100.0),
-                    align_items: AlignItems::Start,
-                    justify_content: JustifyContent::Center,
-                    ..default()
-                },
-                ..default()
-            },
-            OnGameScreen,
-        ))
-        .with_children(|parent| {
-            parent.spawn((
-                TextBundle::from_section(
-                    format!("player: {:?}", game.player),
-                    TextStyle {
-                        font_size: 50.0,
-                        color: Color::GREEN,
-                        ..default()
-                    },
-                )
-                .with_style(Style {
-                    margin: UiRect::all(Val::Px(100.0)),
-                    ..default()
-                }),
-                PlayerTracker,
-                OnGameScreen,
-            ));
-        });
-
-    let mut triangle = Triangle2d {
-        vertices: [
-            Vec2::Y * 0.25,
-            Vec2::new(-0.25, -0.25),
-            Vec2::new(0.25, -0.25),
-        ],
-    };
-    commands.spawn((
-        MaterialMesh2dBundle {
-            mesh: meshes
-                .add(Mesh::from(triangle))
-                .into(),
-            material: materials.add(Color::NAVY).into(),
-            transform: Transform {
-                translation: Vec3::new(0.0, 0.0, 3.0),
-                scale: Vec3::new(60.0, 60.0, 2.0),
-                ..default()
-            },
-            ..default()
-        },
-        OnGameScreen,
-        PlayerSprite,
-    ));
+    commands.insert_resource(ActiveRun);
+
+    let mut level = levels.get(&selected_level.0).clone();
+    // Only changes how the run looks, so replays play back the same.
+    if let Some(settings) = settings {
+        settings.color_scheme.apply(&mut level);
+    }
+    info!("starting run on level {}", level.name);
+
+    let seed = seed_mode.next_seed();
+    info!("starting run with seed {}", seed);
+    commands.insert_resource(GameRng::new(seed));
+
+    // `Game` outlives a single run, so everything a run accumulates is reset
+    // here rather than relying on `init_resource`.
+    game.player = default();
+    game.theta = 0.0;
+    game.arena = Arena::new(level.sides);
+    game.score = 0.0;
+    game.milestone = None;
+    game.killed_by = None;
+    game.over = false;
+    game.pattern = None;
+    game.center_ring_radius = CENTER_HEX_RADIUS;
+    game.player_radius = PLAYER_RING_RADIUS;
+    game.wall_ring_radius = WALL_RING_RADIUS;
+    commands.insert_resource(PreviousTick {
+        theta: game.theta,
+        arena: game.arena,
+        player_theta: game.player.theta,
+        player_radius: game.player_radius,
+        center_ring_radius: game.center_ring_radius,
+    });
 
     commands.insert_resource(GameGlobalTimer(Timer::from_seconds(
         6000.0,
         TimerMode::Once,
     )));
-    commands.insert_resource(GameMoveTimer(Timer::from_seconds(
-        1.0 / 60.0,
-        TimerMode::Repeating,
-    )));
-    commands.insert_resource(GamePlayerTrackerTimer(Timer::from_seconds(
-        1.0 / 60.0,
-        TimerMode::Repeating,
-    )));
-    commands.insert_resource(GamePlayerInputTimer(Timer::from_seconds(
-        1.0 / 60.0,
-        TimerMode::Repeating,
-    )));
-    commands.insert_resource(WallSpawnTimer(Timer::from_seconds(1.0, TimerMode::Repeating)));
-    commands.insert_resource(WallMoveTimer(Timer::from_seconds(
-        1.0 / 30.0,
-        TimerMode::Repeating,
-    )));
-    commands.insert_resource(BackgroundMoveTimer(Timer::from_seconds(
-        1.0 / 30.0,
-        TimerMode::Repeating,
-    )));
-    commands.insert_resource(BorderMoveTimer(Timer::from_seconds(
-        1.0 / 30.0,
-        TimerMode::Repeating,
-    )));
-    commands.insert_resource(CenterHexMoveTimer(Timer::from_seconds(
-        1.0 / 30.0,
-        TimerMode::Repeating,
-    )));
-    commands.insert_resource(GameRotateTimer(Timer::from_seconds(
-        1.0 / 30.0,
+    commands.insert_resource(BeatClock::new(level.beat_map()));
+
+    let speeds = level.speeds(0.0);
+    commands.insert_resource(speeds);
+    commands.insert_resource(Rotation::new(
+        speeds.spin_speed,
+        level.rotation.event_interval.map_or(0.0, |(min, _)| min),
+    ));
+    commands.insert_resource(WallSpawnTimer(Timer::from_seconds(
+        level.spawn_interval,
         TimerMode::Repeating,
     )));
 
-    commands.insert_resource(ScoreTimer(Timer::from_seconds(
-        60.0,
-        TimerMode::Once,
-    )));
+    commands.insert_resource(MorphTimer {
+        timer: Timer::from_seconds(level.morph_interval, TimerMode::Repeating),
+        next: 0,
+    });
 
-    commands.insert_resource(RadiusShrinkerTimer(Timer::from_seconds(
-        1.0 / 60.0,
-        TimerMode::Repeating,
-    )));
+    commands.insert_resource(ScoreTimer(
+        level
+            .duration
+            .map(|duration| Timer::from_seconds(duration, TimerMode::Once)),
+    ));
 
+    current_level.0 = level;
 }
 
-fn game_player_tracker(
-    time: Res<Time>,
-    mut game_player_tracker_timer: ResMut<GamePlayerTrackerTimer>,
-    mut query: Query<&mut Text, (With<OnGameScreen>, With<PlayerTracker>)>,
-    game: Res<Game>,
+fn game_read_input(
+    actions: Res<ActionState>,
+    bindings: Res<Bindings>,
+    mut input: ResMut<PlayerInput>,
 ) {
-    if game_player_tracker_timer
-        .0
-        .tick(time.delta())
-        .just_finished()
-    {
-        for mut text in query.iter_mut() {
-            text.sections[0].value = format!(
-                "player: {:?}\nscore: {:?}\nplayer_ring: {:?}\ncenter_ring: {:?}\nwall_ring: {:?}",
-                game.player,
-                game.score,
-                game.player_radius,
-                game.center_ring_radius,
-                game.wall_ring_radius
-            );
-        }
-    }
+    *input = PlayerInput::new(actions.steer(bindings.both_pressed));
 }
 
 fn game_handle_input(
     time: Res<Time>,
-    mut input_timer: ResMut<GamePlayerInputTimer>,
-    mut input: Res<ButtonInput<KeyCode>>,
+    level: Res<CurrentLevel>,
+    input: Res<PlayerInput>,
     mut game: ResMut<Game>,
+    walls: Query<&Wall>,
 ) {
-    if input_timer.0.tick(time.delta()).just_finished() {
-        if input.pressed(KeyCode::ArrowRight) {
-            game.player.theta = (game.player.theta + PLAYER_MOVE_SPEED) % 360.0;
-        } else if input.pressed(KeyCode::ArrowLeft) {
-            game.player.theta = (game.player.theta - PLAYER_MOVE_SPEED) % 360.0;
-        }
+    let step = input.steer() * level.0.player_speed * time.delta_seconds();
+    if step == 0.0 {
+        return;
     }
+
+    // Moving sideways into a wall stops the player against it instead of
+    // killing them; only walls closing in from the front end the run.
+    game.player.theta = sweep_player(game.player.theta, step, |theta| {
+        walls
+            .iter()
+            .any(|wall| wall.hits_player(&game.arena, game.theta, theta, game.player_radius))
+    });
 }
 
 fn game(
     time: Res<Time>,
     mut global_timer: ResMut<GameGlobalTimer>,
-    mut move_timer: ResMut<GameMoveTimer>,
     mut game_state: ResMut<NextState<GameState>>,
     mut game: ResMut<Game>,
-    mut query: Query<&mut Transform, (With<OnGameScreen>, With<PlayerSprite>)>,
 ) {
     if global_timer.0.tick(time.delta()).finished() {
-        game_state.set(GameState::Menu);
+        game.over = true;
+        game_state.set(GameState::GameOver);
     }
 
-    if move_timer.0.tick(time.delta()).just_finished() {
-        let x = game.player.theta.to_radians().cos() * game.player_radius;
-        let y = game.player.theta.to_radians().sin() * game.player_radius;
-        game.player.x = x;
-        game.player.y = y;
-        for mut transform in query.iter_mut() {
-            transform.translation.x = x;
-            transform.translation.y = y;
-            transform.rotation = Quat::from_rotation_z(game.player.theta.to_radians() + 270.0_f32.to_radians());
-        }
-    }
+    game.player.x = game.player.theta.to_radians().cos() * game.player_radius;
+    game.player.y = game.player.theta.to_radians().sin() * game.player_radius;
+}
+
+fn game_snapshot(game: Res<Game>, mut previous: ResMut<PreviousTick>) {
+    previous.theta = game.theta;
+    previous.arena = game.arena;
+    previous.player_theta = game.player.theta;
+    previous.player_radius = game.player_radius;
+    previous.center_ring_radius = game.center_ring_radius;
 }
 
 fn game_cleanup(mut commands: Commands, query: Query<Entity, With<OnGameScreen>>) {
+    commands.remove_resource::<ActiveRun>();
     for entity in query.iter() {
         commands.entity(entity).despawn_recursive();
     }
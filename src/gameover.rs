@@ -0,0 +1,170 @@
+use crate::controls::{Action, ActionState};
+use crate::highscore::HighScores;
+use crate::levels::CurrentLevel;
+use crate::replay::ReplayPlayback;
+use crate::seed::{GameRng, SeedMode};
+use crate::{Game, GameState};
+use bevy::prelude::*;
+
+#[derive(Component)]
+struct OnGameOverScreen;
+
+#[derive(Resource, Default)]
+struct LastRank(Option<usize>);
+
+pub struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LastRank>()
+            .add_systems(
+                OnEnter(GameState::GameOver),
+                (
+                    // Watching a replay does not set a new time.
+                    gameover_record.run_if(not(resource_exists::<ReplayPlayback>)),
+                    gameover_setup,
+                )
+                    .chain(),
+            )
+            .add_systems(Update, gameover_input.run_if(in_state(GameState::GameOver)))
+            .add_systems(OnExit(GameState::GameOver), gameover_cleanup);
+    }
+}
+
+fn gameover_record(
+    game: Res<Game>,
+    level: Res<CurrentLevel>,
+    mut high_scores: ResMut<HighScores>,
+    mut last_rank: ResMut<LastRank>,
+) {
+    last_rank.0 = high_scores.record(&level.0.name, game.score);
+    if last_rank.0.is_some() {
+        high_scores.save();
+    }
+}
+
+fn summary_text(text: String, font_size: f32, color: Color) -> TextBundle {
+    TextBundle::from_section(
+        text,
+        TextStyle {
+            font_size,
+            color,
+            ..default()
+        },
+    )
+    .with_style(Style {
+        margin: UiRect::all(Val::Px(10.0)),
+        ..default()
+    })
+}
+
+fn gameover_setup(
+    mut commands: Commands,
+    game: Res<Game>,
+    level: Res<CurrentLevel>,
+    high_scores: Res<HighScores>,
+    last_rank: Res<LastRank>,
+    rng: Res<GameRng>,
+    seed_mode: Res<SeedMode>,
+) {
+    let seed = match *seed_mode {
+        SeedMode::Daily => format!("Seed: {} (daily)", rng.seed),
+        _ => format!("Seed: {}", rng.seed),
+    };
+
+    let table = &level.0.name;
+    let killed_by = match &game.killed_by {
+        Some(pattern) => format!("Killed by: {}", pattern),
+        None => "Survived!".to_string(),
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                // the arena stays in view behind, shaken by the death
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.75).into(),
+                ..default()
+            },
+            OnGameOverScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                summary_text("Game Over".to_string(), 100.0, Color::WHITE).with_style(Style {
+                    margin: UiRect::all(Val::Px(50.0)),
+                    ..default()
+                }),
+            );
+            parent.spawn(summary_text(
+                format!("Time: {:.2}", game.score),
+                50.0,
+                Color::WHITE,
+            ));
+            parent.spawn(summary_text(
+                format!(
+                    "Best: {:.2}",
+                    high_scores.best(table).unwrap_or(game.score)
+                ),
+                50.0,
+                Color::BLUE,
+            ));
+            parent.spawn(summary_text(level.0.name.clone(), 40.0, Color::WHITE));
+            if let Some(milestone) = &game.milestone {
+                parent.spawn(summary_text(
+                    format!("Reached: {}", milestone),
+                    30.0,
+                    Color::WHITE,
+                ));
+            }
+            parent.spawn(summary_text(killed_by, 30.0, Color::GRAY));
+            parent.spawn(summary_text(seed, 30.0, Color::GRAY));
+
+            for (rank, entry) in high_scores.top(table).iter().enumerate() {
+                let color = if last_rank.0 == Some(rank) {
+                    Color::YELLOW
+                } else {
+                    Color::WHITE
+                };
+                parent.spawn(summary_text(
+                    format!("{:>2}. {:.2}", rank + 1, entry.time),
+                    24.0,
+                    color,
+                ));
+            }
+
+            parent.spawn(summary_text(
+                "Confirm to retry, Back for menu".to_string(),
+                30.0,
+                Color::GRAY,
+            ));
+        });
+}
+
+fn gameover_input(actions: Res<ActionState>, mut game_state: ResMut<NextState<GameState>>) {
+    if actions.just_pressed(Action::Confirm) {
+        info!("retrying run");
+        game_state.set(GameState::Playing);
+    } else if actions.any_just_pressed([Action::Back, Action::Pause]) {
+        game_state.set(GameState::Menu);
+    }
+}
+
+// The rank only holds for the screen it was recorded for, so a replay's
+// game over screen does not highlight the last live run's time.
+fn gameover_cleanup(
+    query: Query<Entity, With<OnGameOverScreen>>,
+    mut last_rank: ResMut<LastRank>,
+    mut commands: Commands,
+) {
+    last_rank.0 = None;
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,125 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const HIGH_SCORE_VERSION: u32 = 1;
+const HIGH_SCORE_LIMIT: usize = 10;
+const HIGH_SCORE_FILE: &str = "highscores.ron";
+const CONFIG_DIR_NAME: &str = "erbevy";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HighScoreEntry {
+    pub time: f32,
+}
+
+// Top times per table, where a table is a level/difficulty key.
+#[derive(Resource, Debug, Serialize, Deserialize)]
+pub struct HighScores {
+    version: u32,
+    tables: BTreeMap<String, Vec<HighScoreEntry>>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+pub struct HighScorePlugin;
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(HighScores::load(config_dir().join(HIGH_SCORE_FILE)));
+    }
+}
+
+// Prefer the platform config directory, falling back to the working directory
+// on platforms that do not have one.
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir()
+        .map(|dir| dir.join(CONFIG_DIR_NAME))
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+impl HighScores {
+    fn empty(path: PathBuf) -> HighScores {
+        HighScores {
+            version: HIGH_SCORE_VERSION,
+            tables: BTreeMap::new(),
+            path,
+        }
+    }
+
+    // A missing, unreadable or outdated file starts a fresh table instead of
+    // failing the whole game.
+    pub fn load(path: PathBuf) -> HighScores {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => {
+                info!("no high scores at {}", path.display());
+                return HighScores::empty(path);
+            }
+        };
+
+        match ron::from_str::<HighScores>(&contents) {
+            Ok(scores) if scores.version == HIGH_SCORE_VERSION => HighScores { path, ..scores },
+            Ok(scores) => {
+                warn!(
+                    "ignoring high scores at {} with unknown version {}",
+                    path.display(),
+                    scores.version
+                );
+                HighScores::empty(path)
+            }
+            Err(err) => {
+                warn!(
+                    "ignoring corrupt high scores at {}: {}",
+                    path.display(),
+                    err
+                );
+                HighScores::empty(path)
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = self.write(&self.path) {
+            warn!(
+                "could not save high scores to {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+
+    fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    pub fn top(&self, table: &str) -> &[HighScoreEntry] {
+        self.tables.get(table).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn best(&self, table: &str) -> Option<f32> {
+        self.top(table).first().map(|entry| entry.time)
+    }
+
+    // Returns the rank of the new time when it made it into the table.
+    pub fn record(&mut self, table: &str, time: f32) -> Option<usize> {
+        let entries = self.tables.entry(table.to_string()).or_default();
+        let rank = entries
+            .iter()
+            .position(|entry| time > entry.time)
+            .unwrap_or(entries.len());
+        if rank >= HIGH_SCORE_LIMIT {
+            return None;
+        }
+
+        entries.insert(rank, HighScoreEntry { time });
+        entries.truncate(HIGH_SCORE_LIMIT);
+        Some(rank)
+    }
+}
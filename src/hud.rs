@@ -0,0 +1,183 @@
+use crate::game::{ActiveRun, OnGameScreen};
+use crate::highscore::HighScores;
+use crate::levels::CurrentLevel;
+use crate::render::Themed;
+use crate::replay::ReplayPlayback;
+use crate::{Game, GameState};
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+
+const HUD_REFERENCE_HEIGHT: f32 = 720.0;    // window height the font sizes are for
+const HUD_MARGIN: f32 = 3.0;                // percent of the window height
+const TIMER_FONT_SIZE: f32 = 72.0;
+const RECORD_FONT_SIZE: f32 = 24.0;
+const STAGE_FONT_SIZE: f32 = 32.0;
+const BEST_FONT_SIZE: f32 = 28.0;
+const STAGE_BAR_WIDTH: f32 = 20.0;          // percent of the window width
+const STAGE_BAR_HEIGHT: f32 = 1.2;          // percent of the window height
+const STAGE_BAR_TRACK_ALPHA: f32 = 0.25;
+const RECORD_COLOR: Color = Color::YELLOW;
+
+// Text whose font size follows the window's height.
+#[derive(Component)]
+struct HudText(f32);
+
+#[derive(Component)]
+enum HudField {
+    Timer,
+    Record,
+    Stage,
+    StageBar,
+    Best,
+}
+
+// The survival timer and new record marker in the top left, the stage and
+// how far along it the run is in the top right, and the level's best time in
+// the bottom left. Margins and the stage bar are sized relative to the
+// window, and text is scaled along with it.
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                hud_setup.run_if(resource_added::<ActiveRun>),
+                (hud_update, hud_scale).run_if(in_state(GameState::Playing)),
+            )
+                .chain(),
+        );
+    }
+}
+
+fn hud_text(font_size: f32, color: Color) -> (TextBundle, HudText) {
+    (
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size,
+                color,
+                ..default()
+            },
+        ),
+        HudText(font_size),
+    )
+}
+
+fn corner(top: bool, left: bool) -> NodeBundle {
+    let margin = Val::Vh(HUD_MARGIN);
+    NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            flex_direction: FlexDirection::Column,
+            align_items: if left { AlignItems::Start } else { AlignItems::End },
+            top: if top { margin } else { Val::Auto },
+            bottom: if top { Val::Auto } else { margin },
+            left: if left { margin } else { Val::Auto },
+            right: if left { Val::Auto } else { margin },
+            ..default()
+        },
+        ..default()
+    }
+}
+
+fn hud_setup(mut commands: Commands, level: Res<CurrentLevel>) {
+    let color = level.0.palette.hud();
+
+    commands
+        .spawn((corner(true, true), OnGameScreen))
+        .with_children(|parent| {
+            parent.spawn((hud_text(TIMER_FONT_SIZE, color), HudField::Timer, Themed::Hud));
+            parent.spawn((
+                hud_text(RECORD_FONT_SIZE, RECORD_COLOR),
+                HudField::Record,
+            ));
+        });
+
+    commands
+        .spawn((corner(true, false), OnGameScreen))
+        .with_children(|parent| {
+            parent.spawn((hud_text(STAGE_FONT_SIZE, color), HudField::Stage, Themed::Hud));
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Vw(STAGE_BAR_WIDTH),
+                            height: Val::Vh(STAGE_BAR_HEIGHT),
+                            ..default()
+                        },
+                        background_color: color.with_a(STAGE_BAR_TRACK_ALPHA).into(),
+                        ..default()
+                    },
+                    Themed::Hud,
+                ))
+                .with_children(|track| {
+                    track.spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Percent(0.0),
+                                height: Val::Percent(100.0),
+                                ..default()
+                            },
+                            background_color: color.into(),
+                            ..default()
+                        },
+                        HudField::StageBar,
+                        Themed::Hud,
+                    ));
+                });
+        });
+
+    commands
+        .spawn((corner(false, true), OnGameScreen))
+        .with_children(|parent| {
+            parent.spawn((hud_text(BEST_FONT_SIZE, color), HudField::Best, Themed::Hud));
+        });
+}
+
+// Watching a replay shows the best time, but never a new record.
+fn hud_update(
+    game: Res<Game>,
+    level: Res<CurrentLevel>,
+    high_scores: Option<Res<HighScores>>,
+    playback: Option<Res<ReplayPlayback>>,
+    mut texts: Query<(&HudField, &mut Text)>,
+    mut bars: Query<(&HudField, &mut Style)>,
+) {
+    let best = high_scores.and_then(|scores| scores.best(&level.0.name));
+    let record = playback.is_none() && best.is_some_and(|best| game.score > best);
+    let progress = level.0.stage_progress(game.score).unwrap_or(1.0);
+
+    for (field, mut text) in texts.iter_mut() {
+        text.sections[0].value = match field {
+            HudField::Timer => format!("{:.2}", game.score),
+            HudField::Record if record => "NEW RECORD".to_string(),
+            HudField::Stage => game.milestone.clone().unwrap_or_else(|| level.0.name.clone()),
+            HudField::Best => match best {
+                Some(best) => format!("BEST {:.2}", best),
+                None => "BEST --".to_string(),
+            },
+            _ => String::new(),
+        };
+    }
+    for (field, mut style) in bars.iter_mut() {
+        if let HudField::StageBar = field {
+            style.width = Val::Percent(100.0 * progress);
+        }
+    }
+}
+
+// Without a window, as in tests, text stays at its reference size.
+fn hud_scale(
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut texts: Query<(&HudText, &mut Text)>,
+) {
+    let scale = windows
+        .get_single()
+        .map_or(1.0, |window| window.height() / HUD_REFERENCE_HEIGHT);
+    for (size, mut text) in texts.iter_mut() {
+        for section in text.sections.iter_mut() {
+            section.style.font_size = size.0 * scale;
+        }
+    }
+}
@@ -0,0 +1,443 @@
+use crate::music::{BeatMap, DEFAULT_BPM};
+use crate::patterns::{folder_loading, WallPattern};
+use crate::sim::{
+    DEFAULT_SIDES, PLAYER_MOVE_SPEED, WALL_SHRINK_SPEED, WALL_SPAWN_INTERVAL, WALL_SPIN_SPEED,
+};
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext, LoadedFolder};
+use bevy::prelude::*;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use std::path::Path;
+use thiserror::Error;
+
+const LEVEL_FOLDER: &str = "levels";
+
+// A run lasting this long reaches the milestone, and from then on plays at
+// the level's speeds scaled by the milestone's multipliers. A negative
+// `spin_speed` reverses the rotation.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Milestone {
+    pub time: f32,
+    pub name: String,
+    pub wall_speed: f32,
+    pub spawn_rate: f32,
+    pub spin_speed: f32,
+}
+
+// The speeds a run plays at between two milestones.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct Speeds {
+    pub spin_speed: f32,        // degrees per second
+    pub wall_speed: f32,        // posn per second
+    pub spawn_interval: f32,
+}
+
+// How the arena's spin varies on top of the stage's spin speed.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RotationStyle {
+    // seconds between random reversals and bursts, drawn from this range and
+    // shortened by faster stages; `None` leaves them to patterns
+    pub event_interval: Option<(f32, f32)>,
+    // chance a random event is a burst rather than a reversal
+    pub burst_chance: f64,
+    pub burst_speed: f32,       // multiplier
+    pub burst_duration: f32,
+    pub acceleration: f32,      // degrees per second per second
+    pub tilt: f32,              // camera tilt in degrees at full burst
+}
+
+// RGB colors in the 0.0 to 1.0 range. Colors missing from a level file
+// take the default palette's.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Palette {
+    pub background: [[f32; 3]; 2],
+    pub walls: [f32; 3],
+    pub center: [f32; 3],
+    pub player: [f32; 3],
+    pub hud: [f32; 3],
+}
+
+// How a level's colors change over a run, on top of its `palette`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ThemeStyle {
+    // palettes swapped to after the level's own, one every `swap_interval`
+    // seconds, cycling
+    pub palettes: Vec<Palette>,
+    pub swap_interval: f32,
+    pub swap_duration: f32,     // seconds each swap blends over
+    pub hue_speed: f32,         // degrees per second
+    pub flash_duration: f32,    // seconds a new stage flashes towards white
+    // flip every color when a new stage is reached
+    pub invert_on_stage: bool,
+}
+
+// Everything that sets one level apart from another. Fields missing from a
+// level file take the values of the default level.
+#[derive(Asset, TypePath, Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LevelDefinition {
+    pub name: String,
+    // position in the level select, ties are broken by name
+    pub order: u32,
+    pub sides: u32,
+    // side counts the arena morphs through, one every `morph_interval`
+    // seconds, cycling
+    pub morph_sides: Vec<u32>,
+    pub morph_interval: f32,
+    pub spin_speed: f32,        // degrees per second
+    pub player_speed: f32,      // degrees per second
+    pub wall_speed: f32,        // posn per second
+    pub spawn_interval: f32,
+    pub rotation: RotationStyle,
+    // pattern names, or name prefixes up to a `-`, the spawner may pick;
+    // empty allows every pattern
+    pub patterns: Vec<String>,
+    pub palette: Palette,
+    pub theme: ThemeStyle,
+    // track played during a run, with its beat map in a `.beats.ron`
+    // sidecar next to it
+    pub music: Option<String>,
+    // beats per minute when there is no beat map
+    pub bpm: f32,
+    // beat map for a level without a track yet; a track's sidecar replaces it
+    pub beats: Option<BeatMap>,
+    pub milestones: Vec<Milestone>,
+    // a run that lasts this long is survived; `None` plays on until death
+    pub duration: Option<f32>,
+}
+
+// The levels the menu offers, sorted for the level select. `LevelPlugin`
+// keeps it in sync with the level folder; without it only the default level
+// is available.
+#[derive(Resource)]
+pub struct LevelLibrary {
+    folder: Handle<LoadedFolder>,
+    loading: bool,
+    levels: Vec<LevelDefinition>,
+}
+
+// Name of the level the next run is played on.
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct SelectedLevel(pub String);
+
+// The level of the run in progress, fixed when the run starts.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct CurrentLevel(pub LevelDefinition);
+
+#[derive(Default)]
+struct LevelLoader;
+
+#[derive(Debug, Error)]
+enum LevelLoaderError {
+    #[error("could not read level file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse level file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("could not parse beat map: {0}")]
+    BeatMap(ron::error::SpannedError),
+}
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<LevelDefinition>()
+            .init_asset_loader::<LevelLoader>()
+            .init_resource::<LevelLibrary>()
+            .add_systems(Startup, load_levels)
+            .add_systems(Update, sync_level_library);
+    }
+}
+
+impl AssetLoader for LevelLoader {
+    type Asset = LevelDefinition;
+    type Settings = ();
+    type Error = LevelLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<LevelDefinition, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let mut level = ron::de::from_bytes::<LevelDefinition>(&bytes)?;
+
+            // Reading the sidecar through the load context reloads the level
+            // when the beat map is edited.
+            if let Some(track) = level.music.as_ref() {
+                let sidecar = Path::new(track).with_extension("beats.ron");
+                match load_context.read_asset_bytes(sidecar).await {
+                    Ok(bytes) => {
+                        level.beats =
+                            Some(ron::de::from_bytes(&bytes).map_err(LevelLoaderError::BeatMap)?)
+                    }
+                    Err(err) => warn!("{}: no beat map, using {} bpm: {}", track, level.bpm, err),
+                }
+            }
+            Ok(level)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+impl Default for Milestone {
+    fn default() -> Self {
+        Milestone {
+            time: 0.0,
+            name: String::new(),
+            wall_speed: 1.0,
+            spawn_rate: 1.0,
+            spin_speed: 1.0,
+        }
+    }
+}
+
+impl Default for RotationStyle {
+    fn default() -> Self {
+        RotationStyle {
+            event_interval: None,
+            burst_chance: 0.25,
+            burst_speed: 3.0,
+            burst_duration: 0.6,
+            acceleration: 120.0,
+            tilt: 4.0,
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            background: [[0.5, 0.5, 0.0], [1.0, 0.27, 0.0]],
+            walls: [1.0, 1.0, 1.0],
+            center: [0.25, 0.25, 0.0],
+            player: [0.0, 0.0, 0.5],
+            hud: [1.0, 1.0, 1.0],
+        }
+    }
+}
+
+impl Default for ThemeStyle {
+    fn default() -> Self {
+        ThemeStyle {
+            palettes: Vec::new(),
+            swap_interval: 15.0,
+            swap_duration: 2.0,
+            hue_speed: 0.0,
+            flash_duration: 0.5,
+            invert_on_stage: false,
+        }
+    }
+}
+
+impl Palette {
+    pub fn background(&self, index: u32) -> Color {
+        rgb(self.background[index as usize % self.background.len()])
+    }
+
+    pub fn walls(&self) -> Color {
+        rgb(self.walls)
+    }
+
+    pub fn center(&self) -> Color {
+        rgb(self.center)
+    }
+
+    pub fn player(&self) -> Color {
+        rgb(self.player)
+    }
+
+    pub fn hud(&self) -> Color {
+        rgb(self.hud)
+    }
+
+    // Every color `t` of the way from this palette to `other`.
+    pub fn lerp(&self, other: &Palette, t: f32) -> Palette {
+        let mix = |from: [f32; 3], to: [f32; 3]| {
+            [0, 1, 2].map(|channel| from[channel] + (to[channel] - from[channel]) * t)
+        };
+        Palette {
+            background: [0, 1].map(|index| mix(self.background[index], other.background[index])),
+            walls: mix(self.walls, other.walls),
+            center: mix(self.center, other.center),
+            player: mix(self.player, other.player),
+            hud: mix(self.hud, other.hud),
+        }
+    }
+}
+
+fn rgb([r, g, b]: [f32; 3]) -> Color {
+    Color::rgb(r, g, b)
+}
+
+impl Default for LevelDefinition {
+    fn default() -> Self {
+        LevelDefinition {
+            name: "Hexagon".to_string(),
+            order: 0,
+            sides: DEFAULT_SIDES,
+            morph_sides: Vec::new(),
+            morph_interval: 10.0,
+            spin_speed: WALL_SPIN_SPEED,
+            player_speed: PLAYER_MOVE_SPEED,
+            wall_speed: WALL_SHRINK_SPEED,
+            spawn_interval: WALL_SPAWN_INTERVAL,
+            rotation: RotationStyle::default(),
+            patterns: Vec::new(),
+            palette: Palette::default(),
+            theme: ThemeStyle::default(),
+            music: None,
+            bpm: DEFAULT_BPM,
+            beats: None,
+            milestones: Vec::new(),
+            duration: Some(60.0),
+        }
+    }
+}
+
+impl LevelDefinition {
+    pub fn allows(&self, pattern: &WallPattern) -> bool {
+        self.patterns.is_empty()
+            || self.patterns.iter().any(|allowed| {
+                pattern.name == *allowed
+                    || pattern
+                        .name
+                        .strip_prefix(allowed.as_str())
+                        .is_some_and(|rest| rest.starts_with('-'))
+            })
+    }
+
+    // The last milestone a run of `time` seconds has reached.
+    pub fn milestone(&self, time: f32) -> Option<&Milestone> {
+        self.milestones
+            .iter()
+            .rev()
+            .find(|milestone| milestone.time <= time)
+    }
+
+    // How far a run of `time` seconds is from the last milestone reached (or
+    // the start) to the next one, or to the end when none is left. `None`
+    // once there is nothing left to reach.
+    pub fn stage_progress(&self, time: f32) -> Option<f32> {
+        let start = self.milestone(time).map_or(0.0, |milestone| milestone.time);
+        let end = self
+            .milestones
+            .iter()
+            .map(|milestone| milestone.time)
+            .filter(|&end| end > time)
+            .reduce(f32::min)
+            .or(self.duration.filter(|&end| end > time))?;
+        Some(((time - start) / (end - start).max(f32::EPSILON)).clamp(0.0, 1.0))
+    }
+
+    pub fn beat_map(&self) -> BeatMap {
+        self.beats.clone().unwrap_or(BeatMap {
+            bpm: self.bpm,
+            ..default()
+        })
+    }
+
+    // The speeds at `time` seconds into a run: the level's own before the
+    // first milestone, scaled by the last milestone reached after it.
+    pub fn speeds(&self, time: f32) -> Speeds {
+        let base = Milestone::default();
+        let milestone = self.milestone(time).unwrap_or(&base);
+        Speeds {
+            spin_speed: self.spin_speed * milestone.spin_speed,
+            wall_speed: self.wall_speed * milestone.wall_speed,
+            spawn_interval: self.spawn_interval / milestone.spawn_rate.max(f32::EPSILON),
+        }
+    }
+}
+
+impl Default for LevelLibrary {
+    fn default() -> Self {
+        LevelLibrary::new(Vec::new())
+    }
+}
+
+impl LevelLibrary {
+    // Keeps the default level when given none, so there is always a level
+    // to play.
+    pub fn new(mut levels: Vec<LevelDefinition>) -> LevelLibrary {
+        if levels.is_empty() {
+            levels.push(LevelDefinition::default());
+        }
+        levels.sort_by(|a, b| (a.order, &a.name).cmp(&(b.order, &b.name)));
+        LevelLibrary {
+            folder: Handle::default(),
+            loading: false,
+            levels,
+        }
+    }
+
+    pub fn levels(&self) -> &[LevelDefinition] {
+        &self.levels
+    }
+
+    // True until the level folder has finished loading, or failed to.
+    pub fn is_loading(&self) -> bool {
+        self.loading
+    }
+
+    // The named level, falling back to the first one when it does not exist
+    // (any more).
+    pub fn get(&self, name: &str) -> &LevelDefinition {
+        self.levels
+            .iter()
+            .find(|level| level.name == name)
+            .unwrap_or(&self.levels[0])
+    }
+
+    // The level `offset` places after the named one in the level select,
+    // wrapping around.
+    pub fn cycle(&self, name: &str, offset: isize) -> &LevelDefinition {
+        let index = self
+            .levels
+            .iter()
+            .position(|level| level.name == name)
+            .unwrap_or_default();
+        let index = (index as isize + offset).rem_euclid(self.levels.len() as isize);
+        &self.levels[index as usize]
+    }
+}
+
+fn load_levels(mut library: ResMut<LevelLibrary>, asset_server: Res<AssetServer>) {
+    library.folder = asset_server.load_folder(LEVEL_FOLDER);
+    library.loading = true;
+}
+
+// Rebuilds the library whenever a level file is added, edited or removed,
+// and once the folder has finished loading. Falls back to the default level
+// while nothing is loaded.
+fn sync_level_library(
+    mut events: EventReader<AssetEvent<LevelDefinition>>,
+    levels: Res<Assets<LevelDefinition>>,
+    asset_server: Res<AssetServer>,
+    mut library: ResMut<LevelLibrary>,
+) {
+    let loading = library.loading && folder_loading(&asset_server, &library.folder);
+    if events.read().count() == 0 && loading == library.loading {
+        return;
+    }
+
+    let folder = library.folder.clone();
+    let loaded: Vec<LevelDefinition> = levels.iter().map(|(_, level)| level.clone()).collect();
+    if !loaded.is_empty() {
+        info!("loaded {} levels", loaded.len());
+    }
+    *library = LevelLibrary::new(loaded);
+    library.folder = folder;
+    library.loading = loading;
+}
@@ -0,0 +1,104 @@
+#![allow(unused)]
+
+use bevy::input::InputPlugin;
+use bevy::prelude::*;
+
+pub mod camera;
+pub mod controls;
+pub mod debug;
+pub mod game;
+pub mod gameover;
+pub mod highscore;
+pub mod hud;
+pub mod levels;
+pub mod menu;
+pub mod music;
+pub mod patterns;
+pub mod pause;
+pub mod render;
+pub mod replay;
+pub mod seed;
+pub mod settings;
+pub mod sim;
+pub mod sound;
+pub mod theme;
+
+use controls::ControlsPlugin;
+use game::GamePlugin;
+use seed::SeedMode;
+use sim::Arena;
+
+#[derive(Clone, Eq, PartialEq, Debug, Default, Hash, States)]
+pub enum GameState {
+    #[default]
+    Menu,
+    Controls,
+    Playing,
+    Paused,
+    GameOver,
+    Exit,
+}
+
+const NUM_RINGS: usize = 10;
+const NUM_SECTIONS: usize = 10;
+
+#[derive(Resource)]
+struct PhaseTimer(Timer);
+
+#[derive(Default, Debug)]
+struct Section {
+    color: u32,
+}
+
+#[derive(Default, Debug)]
+struct Ring {
+    sections: [Section; NUM_SECTIONS],
+}
+
+#[derive(Resource, Default, Debug)]
+pub struct Game {
+    rings: [Ring; NUM_RINGS],
+    pub player: Player,
+    walls: Walls,
+    pub theta: f32,
+    pub arena: Arena,
+    pub score: f32,
+    pub milestone: Option<String>,
+    pub killed_by: Option<String>,
+    // set on the tick the run ends, so ticks left in the same frame skip it
+    pub over: bool,
+    // index in the pattern library of the pattern spawned last
+    pub pattern: Option<usize>,
+    pub center_ring_radius: f32,
+    pub player_radius: f32,
+    pub wall_ring_radius: f32,
+}
+
+#[derive(Default, Debug)]
+pub struct Player {
+    pub x: f32,
+    pub y: f32,
+    pub theta: f32,
+    pos: u32,
+}
+
+#[derive(Default, Debug)]
+struct Walls {
+    last: u32,
+}
+
+// The simulation alone, without a window, renderer or menus: step it with
+// `App::update` to run the game in tests or tools.
+pub fn headless_app(seed_mode: SeedMode) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        InputPlugin,
+        ControlsPlugin::default(),
+        GamePlugin::default(),
+    ))
+        .init_resource::<Game>()
+        .insert_resource(seed_mode)
+        .init_state::<GameState>();
+    app
+}
@@ -1,86 +1,68 @@
-#![allow(unused)]
-
 use bevy::prelude::*;
 
-mod game;
-mod menu;
-
-use game::GamePlugin;
-use menu::MenuPlugin;
-
-#[derive(Clone, Eq, PartialEq, Debug, Default, Hash, States)]
-enum GameState {
-    #[default]
-    Menu,
-    Playing,
-    Paused,
-    Exit,
-}
-
-const NUM_RINGS: usize = 10;
-const NUM_SECTIONS: usize = 10;
-
-#[derive(Resource)]
-struct PhaseTimer(Timer);
-
-#[derive(Default, Debug)]
-struct Section {
-    color: u32,
-}
-
-#[derive(Default, Debug)]
-struct Ring {
-    sections: [Section; NUM_SECTIONS],
-}
-
-#[derive(Resource, Default, Debug)]
-struct Game {
-    rings: [Ring; NUM_RINGS],
-    menu: Menu,
-    player: Player,
-    walls: Walls,
-    theta: f32,
-    score: f32,
-    center_ring_radius: f32,
-    player_radius: f32,
-    wall_ring_radius: f32,
-}
-
-#[derive(Default, Debug)]
-struct Player {
-    x: f32,
-    y: f32,
-    theta: f32,
-    pos: u32,
-}
-
-#[derive(Default, Debug)]
-struct Walls {
-    last: u32,
-}
-
-#[derive(Component, Default)]
-struct MenuItem {
-    text: String,
-}
-
-#[derive(Default, Debug)]
-struct Menu {
-    hover: u32,
-}
+use erbevy::camera::CameraPlugin;
+use erbevy::controls::{ControlsPlugin, ControlsScreenPlugin, CONTROLS_FILE};
+use erbevy::debug::DebugOverlayPlugin;
+use erbevy::game::GamePlugin;
+use erbevy::highscore::config_dir;
+use erbevy::gameover::GameOverPlugin;
+use erbevy::highscore::HighScorePlugin;
+use erbevy::hud::HudPlugin;
+use erbevy::levels::LevelPlugin;
+use erbevy::menu::MenuPlugin;
+use erbevy::music::MusicPlugin;
+use erbevy::patterns::PatternPlugin;
+use erbevy::pause::PausePlugin;
+use erbevy::render::GameRenderPlugin;
+use erbevy::replay::{replay_dir, Replay, ReplayControlsPlugin, ReplayPlugin};
+use erbevy::seed::SeedMode;
+use erbevy::settings::{Settings, SettingsPlugin, SETTINGS_FILE};
+use erbevy::sound::SoundPlugin;
+use erbevy::{Game, GameState};
 
 fn setup_cameras(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // Read before the window is created so it opens at the saved size.
+    let (settings, warnings) = Settings::load(config_dir().join(SETTINGS_FILE));
+
     App::new()
         .add_plugins((
-            DefaultPlugins.set(bevy::log::LogPlugin { ..default() }),
+            DefaultPlugins
+                .set(bevy::log::LogPlugin { ..default() })
+                .set(WindowPlugin {
+                    primary_window: Some(settings.window()),
+                    ..default()
+                }),
+            (
+                SettingsPlugin { settings, warnings },
+                ControlsPlugin {
+                    bindings_path: Some(config_dir().join(CONTROLS_FILE)),
+                },
+                ControlsScreenPlugin,
+            ),
             MenuPlugin,
-            GamePlugin,
+            GamePlugin::default(),
+            (GameRenderPlugin, HudPlugin, DebugOverlayPlugin, CameraPlugin),
+            PatternPlugin,
+            LevelPlugin,
+            MusicPlugin,
+            SoundPlugin,
+            PausePlugin,
+            GameOverPlugin,
+            HighScorePlugin,
+            ReplayControlsPlugin,
         ))
+        // Arguments are parsed once `LogPlugin` is up to report bad ones.
+        .add_plugins(ReplayPlugin {
+            playback: Replay::from_args(args.clone()),
+            save_dir: Some(replay_dir()),
+        })
         .init_resource::<Game>()
+        .insert_resource(SeedMode::from_args(args))
         .init_state::<GameState>()
         .add_systems(Startup, setup_cameras)
         .add_systems(
@@ -1,23 +1,90 @@
-use crate::{Game, GameState};
+use crate::controls::{Action, ActionState};
+use crate::highscore::HighScores;
+use crate::levels::{LevelLibrary, SelectedLevel};
+use crate::settings::{SettingItem, Settings, SETTING_ITEMS};
+use crate::sound::{AudioSettings, Sfx};
+use crate::GameState;
 use bevy::prelude::*;
 
-#[derive(Component)]
-struct OnMenuScreen;
+const MENU_ITEM_COLOR: Color = Color::BLUE;
+const MENU_HOVER_COLOR: Color = Color::WHITE;
+const MENU_HIGHLIGHT_SPEED: f32 = 8.0;      // per second
+const MENU_HIGHLIGHT_INDENT: f32 = 24.0;    // pixels
+// pages with more items than this use a smaller font to fit
+const MENU_LONG_PAGE: usize = 6;
 
-#[derive(Resource)]
-struct MenuUpdateTimer(Timer);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MenuPage {
+    Main,
+    LevelSelect,
+    Settings,
+    HighScores,
+    Credits,
+}
 
+// What choosing a menu item does. Keys, gamepads and the mouse all send
+// these, and `menu_run_commands` carries them out.
+#[derive(Event, Debug, Clone, PartialEq)]
+enum MenuCommand {
+    Play,
+    Open(MenuPage),
+    SelectLevel(String),
+    // moves a setting along by -1 or 1
+    Adjust(SettingItem, i32),
+    Enter(GameState),
+    Back,
+}
+
+struct MenuEntry {
+    label: String,
+    command: MenuCommand,
+}
+
+// Everything a page shows: a title, lines of information and the items that
+// can be focused and chosen.
+struct PageContents {
+    title: &'static str,
+    lines: Vec<String>,
+    items: Vec<MenuEntry>,
+}
+
+// The open pages, the shown one last, each with its focused item so backing
+// out of a submenu returns to where it was opened from. Kept while the menu
+// is left, so the controls screen returns to the settings.
 #[derive(Resource)]
-struct MenuInputTimer(Timer);
+struct MenuStack(Vec<(MenuPage, usize)>);
+
+// The root of the shown page.
+#[derive(Component)]
+struct OnMenuScreen(MenuPage);
+
+#[derive(Component)]
+struct MenuItem {
+    index: usize,
+    command: MenuCommand,
+}
+
+// How far an item has eased into its focused look, from 0.0 to 1.0.
+#[derive(Component, Default)]
+struct MenuHighlight(f32);
 
 pub struct MenuPlugin;
 
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(GameState::Menu), menu_setup)
+        app.add_event::<Sfx>()
+            .add_event::<MenuCommand>()
+            .init_resource::<MenuStack>()
             .add_systems(
                 Update,
-                (menu_input, menu_update)
+                (
+                    menu_keys,
+                    menu_mouse,
+                    menu_level_select,
+                    menu_run_commands,
+                    menu_refresh,
+                    menu_highlight,
+                )
                     .chain()
                     .run_if(in_state(GameState::Menu)),
             )
@@ -25,146 +92,396 @@ impl Plugin for MenuPlugin {
     }
 }
 
-fn menu_setup(mut commands: Commands) {
+impl Default for MenuStack {
+    fn default() -> Self {
+        MenuStack(vec![(MenuPage::Main, 0)])
+    }
+}
+
+impl MenuStack {
+    fn page(&self) -> MenuPage {
+        self.0.last().map_or(MenuPage::Main, |(page, _)| *page)
+    }
+
+    fn hover(&self) -> usize {
+        self.0.last().map_or(0, |(_, hover)| *hover)
+    }
+
+    fn set_hover(&mut self, hover: usize) {
+        if let Some(top) = self.0.last_mut() {
+            top.1 = hover;
+        }
+    }
+}
+
+fn entry(label: impl Into<String>, command: MenuCommand) -> MenuEntry {
+    MenuEntry {
+        label: label.into(),
+        command,
+    }
+}
+
+// The resources pages are built from.
+struct MenuSources<'a> {
+    levels: &'a LevelLibrary,
+    high_scores: &'a HighScores,
+    selected_level: &'a str,
+    settings: &'a Settings,
+    audio: Option<&'a AudioSettings>,
+}
+
+fn page_contents(page: MenuPage, sources: &MenuSources) -> PageContents {
+    let MenuSources {
+        levels,
+        high_scores,
+        selected_level,
+        settings,
+        audio,
+    } = *sources;
+    let level = levels.get(selected_level);
+    let back = || entry("Back", MenuCommand::Back);
+
+    match page {
+        MenuPage::Main => PageContents {
+            title: "Welcome to the menu!",
+            lines: vec![format!("< {} >", level.name)],
+            items: vec![
+                entry("Play", MenuCommand::Play),
+                entry("Level Select", MenuCommand::Open(MenuPage::LevelSelect)),
+                entry("Settings", MenuCommand::Open(MenuPage::Settings)),
+                entry("High Scores", MenuCommand::Open(MenuPage::HighScores)),
+                entry("Credits", MenuCommand::Open(MenuPage::Credits)),
+                entry("Quit", MenuCommand::Enter(GameState::Exit)),
+            ],
+        },
+        MenuPage::LevelSelect => PageContents {
+            title: "Level Select",
+            lines: Vec::new(),
+            items: levels
+                .levels()
+                .iter()
+                .map(|level| {
+                    let best = high_scores
+                        .best(&level.name)
+                        .map_or("-".to_string(), |time| format!("{:.2}", time));
+                    entry(
+                        format!("{}  {}", level.name, best),
+                        MenuCommand::SelectLevel(level.name.clone()),
+                    )
+                })
+                .collect(),
+        },
+        MenuPage::Settings => PageContents {
+            title: "Settings",
+            lines: vec!["Left and right change the focused setting".to_string()],
+            items: SETTING_ITEMS
+                .iter()
+                .filter(|item| item.available(audio))
+                .map(|item| entry(item.label(settings, audio), MenuCommand::Adjust(*item, 1)))
+                .chain([
+                    entry("Controls", MenuCommand::Enter(GameState::Controls)),
+                    back(),
+                ])
+                .collect(),
+        },
+        MenuPage::HighScores => PageContents {
+            title: "High Scores",
+            lines: std::iter::once(level.name.clone())
+                .chain(
+                    high_scores
+                        .top(&level.name)
+                        .iter()
+                        .enumerate()
+                        .map(|(rank, entry)| format!("{:>2}. {:.2}", rank + 1, entry.time)),
+                )
+                .collect(),
+            items: vec![back()],
+        },
+        MenuPage::Credits => PageContents {
+            title: "Credits",
+            lines: vec![
+                "erbevy".to_string(),
+                "Made with Bevy".to_string(),
+                "Inspired by Super Hexagon".to_string(),
+            ],
+            items: vec![back()],
+        },
+    }
+}
+
+fn menu_text(text: String, font_size: f32, color: Color) -> TextBundle {
+    TextBundle::from_section(
+        text,
+        TextStyle {
+            font_size,
+            color,
+            ..default()
+        },
+    )
+    .with_style(Style {
+        margin: UiRect::all(Val::Px(10.0)),
+        ..default()
+    })
+}
+
+fn spawn_page(commands: &mut Commands, page: MenuPage, hover: usize, contents: PageContents) {
+    // long pages shrink to fit the window
+    let long = contents.items.len() > MENU_LONG_PAGE;
+    let (title_size, title_margin) = if long { (60.0, 20.0) } else { (80.0, 40.0) };
+    let (font_size, item_margin) = if long { (26.0, 3.0) } else { (50.0, 10.0) };
+
     commands
         .spawn((
             NodeBundle {
                 style: Style {
                     width: Val::Percent(100.0),
                     height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
                     align_items: AlignItems::Center,
                     justify_content: JustifyContent::Center,
                     ..default()
                 },
+                background_color: Color::BLACK.into(),
                 ..default()
             },
-            OnMenuScreen,
+            OnMenuScreen(page),
         ))
         .with_children(|parent| {
-            parent
-                .spawn(NodeBundle {
-                    style: Style {
-                        flex_direction: FlexDirection::Column,
-                        align_items: AlignItems::Center,
-                        width: Val::Percent(100.0),
-                        height: Val::Percent(100.0),
+            parent.spawn(
+                menu_text(contents.title.to_string(), title_size, Color::WHITE).with_style(Style {
+                    margin: UiRect::all(Val::Px(title_margin)),
+                    ..default()
+                }),
+            );
+
+            for line in contents.lines {
+                parent.spawn(menu_text(line, 30.0, Color::GRAY));
+            }
+
+            for (index, item) in contents.items.into_iter().enumerate() {
+                parent.spawn((
+                    menu_text(item.label, font_size, MENU_ITEM_COLOR).with_style(Style {
+                        margin: UiRect::all(Val::Px(item_margin)),
                         ..default()
+                    }),
+                    Interaction::default(),
+                    MenuItem {
+                        index,
+                        command: item.command,
                     },
-                    background_color: Color::BLACK.into(),
-                    ..default()
-                })
-                .with_children(|parent| {
-                    parent.spawn(
-                        TextBundle::from_section(
-                            "Welcome to the menu!",
-                            TextStyle {
-                                font_size: 100.0,
-                                color: Color::WHITE,
-                                ..default()
-                            },
-                        )
-                        .with_style(Style {
-                            margin: UiRect::all(Val::Px(50.0)),
-                            ..default()
-                        }),
-                    );
-
-                    parent.spawn((
-                        TextBundle::from_section(
-                            "Start",
-                            TextStyle {
-                                font_size: 50.0,
-                                color: Color::BLUE,
-                                ..default()
-                            },
-                        )
-                        .with_style(Style {
-                            margin: UiRect::all(Val::Px(10.0)),
-                            ..default()
-                        }),
-                        OnMenuScreen,
-                    ));
-
-                    parent.spawn((
-                        TextBundle::from_section(
-                            "Exit",
-                            TextStyle {
-                                font_size: 50.0,
-                                color: Color::BLUE,
-                                ..default()
-                            },
-                        )
-                        .with_style(Style {
-                            margin: UiRect::all(Val::Px(10.0)),
-                            ..default()
-                        }),
-                        OnMenuScreen,
-                    ));
-                });
+                    // a rebuilt page keeps its focused item lit
+                    MenuHighlight(if index == hover { 1.0 } else { 0.0 }),
+                ));
+            }
         });
+}
 
-    commands.insert_resource(MenuInputTimer(Timer::from_seconds(
-        1.0 / 60.0,
-        TimerMode::Repeating,
-    )));
-    commands.insert_resource(MenuUpdateTimer(Timer::from_seconds(
-        1.0 / 60.0,
-        TimerMode::Repeating,
-    )));
-}
-
-fn menu_input(
-    mut timer: ResMut<MenuInputTimer>,
-    time: Res<Time>,
-    mut game: ResMut<Game>,
-    input: Res<ButtonInput<KeyCode>>,
-    mut game_state: ResMut<NextState<GameState>>,
+fn menu_keys(
+    actions: Res<ActionState>,
+    items: Query<&MenuItem>,
+    mut stack: ResMut<MenuStack>,
+    mut menu_commands: EventWriter<MenuCommand>,
+    mut sounds: EventWriter<Sfx>,
 ) {
-    if timer.0.tick(time.delta()).just_finished() {
-        if input.just_pressed(KeyCode::ArrowUp) {
-            info!("handling KeyCode::ArrowUp");
-            game.menu.hover = (game.menu.hover.wrapping_sub(1)) % 2;
+    if actions.any_just_pressed([Action::Back, Action::Pause]) {
+        menu_commands.send(MenuCommand::Back);
+    }
+
+    let count = items.iter().count();
+    if count == 0 {
+        return;
+    }
+    let hover = stack.hover().min(count - 1);
+    if actions.just_pressed(Action::Up) {
+        stack.set_hover((hover + count - 1) % count);
+        sounds.send(Sfx::MenuMove);
+    }
+    if actions.just_pressed(Action::Down) {
+        stack.set_hover((hover + 1) % count);
+        sounds.send(Sfx::MenuMove);
+    }
+    let focused = items.iter().find(|item| item.index == hover);
+    if actions.just_pressed(Action::Confirm) {
+        if let Some(item) = focused {
+            menu_commands.send(item.command.clone());
         }
-        if input.just_pressed(KeyCode::ArrowDown) {
-            info!("handling KeyCode::ArrowDown");
-            game.menu.hover = (game.menu.hover + 1) % 2;
+    }
+    if let Some(MenuCommand::Adjust(setting, _)) = focused.map(|item| &item.command) {
+        for (action, step) in [(Action::RotateLeft, -1), (Action::RotateRight, 1)] {
+            if actions.just_pressed(action) {
+                menu_commands.send(MenuCommand::Adjust(*setting, step));
+            }
         }
-        if input.just_pressed(KeyCode::Enter) {
-            info!("handling KeyCode::Enter");
-            match game.menu.hover {
-                0 => game_state.set(GameState::Playing),
-                1 => game_state.set(GameState::Exit),
-                _ => {}
+    }
+}
+
+// Hovering an item focuses it and clicking chooses it.
+fn menu_mouse(
+    items: Query<(&Interaction, &MenuItem), Changed<Interaction>>,
+    mut stack: ResMut<MenuStack>,
+    mut menu_commands: EventWriter<MenuCommand>,
+    mut sounds: EventWriter<Sfx>,
+) {
+    for (interaction, item) in items.iter() {
+        match interaction {
+            Interaction::Hovered if stack.hover() != item.index => {
+                stack.set_hover(item.index);
+                sounds.send(Sfx::MenuMove);
             }
+            Interaction::Pressed => {
+                stack.set_hover(item.index);
+                menu_commands.send(item.command.clone());
+            }
+            _ => {}
         }
     }
 }
 
-fn menu_update(
-    mut query: Query<&mut Text, With<OnMenuScreen>>,
-    game: Res<Game>,
-    mut timer: ResMut<MenuUpdateTimer>,
-    time: Res<Time>,
+// Left and right cycle through the levels without opening the level select.
+fn menu_level_select(
+    actions: Res<ActionState>,
+    stack: Res<MenuStack>,
+    levels: Res<LevelLibrary>,
+    mut selected_level: ResMut<SelectedLevel>,
+    mut sounds: EventWriter<Sfx>,
+) {
+    if stack.page() != MenuPage::Main {
+        return;
+    }
+    let offset = if actions.just_pressed(Action::RotateRight) {
+        1
+    } else if actions.just_pressed(Action::RotateLeft) {
+        -1
+    } else {
+        return;
+    };
+
+    let level = levels.cycle(&selected_level.0, offset);
+    info!("selected level {}", level.name);
+    selected_level.0 = level.name.clone();
+    sounds.send(Sfx::MenuMove);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn menu_run_commands(
+    mut menu_commands: EventReader<MenuCommand>,
+    mut stack: ResMut<MenuStack>,
+    levels: Res<LevelLibrary>,
+    mut selected_level: ResMut<SelectedLevel>,
+    mut settings: ResMut<Settings>,
+    mut audio: Option<ResMut<AudioSettings>>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut sounds: EventWriter<Sfx>,
 ) {
-    if timer.0.tick(time.delta()).just_finished() {
-        let hover = game.menu.hover;
-        for mut text in query.iter_mut() {
-            for value in text.sections.iter_mut() {
-                if value.value.contains("Start") {
-                    if hover == 0 {
-                        value.value = "> Start".to_string();
-                    } else {
-                        value.value = value.value.replace("> ", "");
-                    }
-                } else if value.value.contains("Exit") {
-                    if hover == 1 {
-                        value.value = "> Exit".to_string();
-                    } else {
-                        value.value = value.value.replace("> ", "");
-                    }
-                }
+    for command in menu_commands.read() {
+        info!("menu: {:?}", command);
+        match command {
+            MenuCommand::Back if stack.0.len() <= 1 => continue,
+            MenuCommand::Back => {
+                stack.0.pop();
+            }
+            MenuCommand::Play => game_state.set(GameState::Playing),
+            MenuCommand::Open(page) => {
+                // The level select opens on the level already selected.
+                let hover = match page {
+                    MenuPage::LevelSelect => levels
+                        .levels()
+                        .iter()
+                        .position(|level| level.name == selected_level.0)
+                        .unwrap_or_default(),
+                    _ => 0,
+                };
+                stack.0.push((*page, hover));
             }
+            MenuCommand::SelectLevel(name) => {
+                selected_level.0 = name.clone();
+                stack.0.pop();
+            }
+            MenuCommand::Adjust(setting, step) => {
+                setting.adjust(*step, &mut settings, audio.as_deref_mut())
+            }
+            MenuCommand::Enter(state) => game_state.set(state.clone()),
+        }
+        sounds.send(Sfx::MenuSelect);
+    }
+}
+
+// Spawns the shown page, and rebuilds it when another one is opened or what
+// it shows changes under it.
+#[allow(clippy::too_many_arguments)]
+fn menu_refresh(
+    mut commands: Commands,
+    stack: Res<MenuStack>,
+    roots: Query<(Entity, &OnMenuScreen)>,
+    levels: Res<LevelLibrary>,
+    high_scores: Res<HighScores>,
+    selected_level: Res<SelectedLevel>,
+    settings: Res<Settings>,
+    audio: Option<Res<AudioSettings>>,
+) {
+    let page = stack.page();
+    let shown = roots.get_single().ok();
+    if shown.is_some_and(|(_, root)| root.0 == page)
+        && !levels.is_changed()
+        && !high_scores.is_changed()
+        && !selected_level.is_changed()
+        && !settings.is_changed()
+        && !audio.as_ref().is_some_and(|audio| audio.is_changed())
+    {
+        return;
+    }
+
+    if let Some((entity, _)) = shown {
+        commands.entity(entity).despawn_recursive();
+    }
+    let sources = MenuSources {
+        levels: &levels,
+        high_scores: &high_scores,
+        selected_level: &selected_level.0,
+        settings: &settings,
+        audio: audio.as_deref(),
+    };
+    spawn_page(&mut commands, page, stack.hover(), page_contents(page, &sources));
+}
+
+fn mix(from: Color, to: Color, t: f32) -> Color {
+    let [r0, g0, b0, a0] = from.as_rgba_f32();
+    let [r1, g1, b1, a1] = to.as_rgba_f32();
+    Color::rgba(
+        r0 + (r1 - r0) * t,
+        g0 + (g1 - g0) * t,
+        b0 + (b1 - b0) * t,
+        a0 + (a1 - a0) * t,
+    )
+}
+
+// Eases the focused item in and the others out: it brightens and gains a
+// backdrop that widens around it.
+fn menu_highlight(
+    time: Res<Time<Real>>,
+    stack: Res<MenuStack>,
+    mut items: Query<(
+        &MenuItem,
+        &mut MenuHighlight,
+        &mut Text,
+        &mut Style,
+        &mut BackgroundColor,
+    )>,
+) {
+    let step = MENU_HIGHLIGHT_SPEED * time.delta_seconds();
+    for (item, mut highlight, mut text, mut style, mut background) in items.iter_mut() {
+        let target = if item.index == stack.hover() { 1.0 } else { 0.0 };
+        if highlight.0 == target && !highlight.is_added() {
+            continue;
+        }
+        highlight.0 += (target - highlight.0).clamp(-step, step);
+
+        let eased = highlight.0 * (2.0 - highlight.0);
+        for section in text.sections.iter_mut() {
+            section.style.color = mix(MENU_ITEM_COLOR, MENU_HOVER_COLOR, eased);
         }
+        style.padding = UiRect::horizontal(Val::Px(MENU_HIGHLIGHT_INDENT * eased));
+        background.0 = Color::rgba(1.0, 1.0, 1.0, 0.15 * eased);
     }
 }
 
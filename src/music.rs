@@ -0,0 +1,215 @@
+use crate::game::ActiveRun;
+use crate::levels::CurrentLevel;
+use crate::sound::AudioSettings;
+use crate::GameState;
+use bevy::asset::LoadState;
+use bevy::audio::Volume;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use serde::Deserialize;
+
+pub const DEFAULT_BPM: f32 = 120.0;
+const PULSE_DECAY: f32 = 8.0;           // per second
+const MUSIC_FADE_SPEED: f32 = 1.5;      // volume per second
+
+// Beat timing for a level's music, read from a `.beats.ron` sidecar next to
+// the track. Levels without one pulse at their own `bpm`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BeatMap {
+    pub bpm: f32,
+    pub offset: f32,            // seconds before the first beat
+    // beat times in seconds; past the last one beats carry on at `bpm`
+    pub beats: Vec<f32>,
+}
+
+// Run time measured in beats. Advanced by the simulation rather than read
+// from the audio, so the pulse stays deterministic for replays and headless
+// runs; the track is started with the run and plays in step with it.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct BeatClock {
+    map: BeatMap,
+    time: f32,
+}
+
+// Plays the current level's track while a run is in progress: it pauses
+// with the game, fades out when the run ends and crossfades into a fresh
+// copy on retry.
+pub struct MusicPlugin;
+
+// Tracks that failed to load, which later runs play without rather than
+// trying again.
+#[derive(Resource, Default)]
+struct MissingTracks(HashSet<String>);
+
+// A playing track and the volume it fades towards, before the mixer's music
+// volume; despawned once faded out.
+#[derive(Component)]
+struct Music {
+    volume: f32,
+    target: f32,
+}
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MissingTracks>()
+            .add_systems(
+                Update,
+                (
+                    music_start.run_if(resource_added::<ActiveRun>),
+                    music_missing,
+                    music_fade,
+                )
+                    .chain(),
+            )
+            .add_systems(OnEnter(GameState::Paused), music_pause)
+            .add_systems(OnExit(GameState::Paused), music_resume)
+            .add_systems(OnEnter(GameState::GameOver), music_stop)
+            .add_systems(OnEnter(GameState::Menu), music_stop);
+    }
+}
+
+impl Default for BeatMap {
+    fn default() -> Self {
+        BeatMap {
+            bpm: DEFAULT_BPM,
+            offset: 0.0,
+            beats: Vec::new(),
+        }
+    }
+}
+
+impl BeatClock {
+    pub fn new(map: BeatMap) -> BeatClock {
+        BeatClock { map, time: 0.0 }
+    }
+
+    pub fn advance(&mut self, delta_seconds: f32) {
+        self.time += delta_seconds;
+    }
+
+    // The number of beats so far and the time of the latest one.
+    fn latest_beat(&self) -> Option<(u32, f32)> {
+        let beats = &self.map.beats;
+        let passed = beats.partition_point(|beat| *beat <= self.time);
+        if passed < beats.len() {
+            return (passed > 0).then(|| (passed as u32, beats[passed - 1]));
+        }
+
+        let start = beats.last().copied().unwrap_or(self.map.offset);
+        if self.time < start {
+            return None;
+        }
+        let extra = if self.map.bpm > 0.0 {
+            ((self.time - start) * self.map.bpm / 60.0).floor()
+        } else {
+            0.0
+        };
+        Some((
+            (passed as u32).max(1) + extra as u32,
+            start + extra * 60.0 / self.map.bpm.max(f32::EPSILON),
+        ))
+    }
+
+    pub fn beat(&self) -> u32 {
+        self.latest_beat().map_or(0, |(count, _)| count)
+    }
+
+    // 1.0 on a beat, decaying towards 0.0 until the next one.
+    pub fn pulse(&self) -> f32 {
+        self.latest_beat()
+            .map_or(0.0, |(_, at)| (-(self.time - at) * PULSE_DECAY).exp())
+    }
+}
+
+fn music_start(
+    mut commands: Commands,
+    level: Res<CurrentLevel>,
+    asset_server: Res<AssetServer>,
+    missing: Res<MissingTracks>,
+    mut playing: Query<&mut Music>,
+) {
+    for mut music in playing.iter_mut() {
+        music.target = 0.0;
+    }
+
+    let track = level.0.music.as_ref().filter(|track| !missing.0.contains(*track));
+    if let Some(track) = track {
+        info!("playing {}", track);
+        commands.spawn((
+            AudioBundle {
+                source: asset_server.load(track.clone()),
+                settings: PlaybackSettings::LOOP.with_volume(Volume::new(0.0)),
+            },
+            Music {
+                volume: 0.0,
+                target: 1.0,
+            },
+        ));
+    }
+}
+
+// The run goes on in silence when its track cannot be loaded.
+fn music_missing(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut missing: ResMut<MissingTracks>,
+    query: Query<(Entity, &Handle<AudioSource>), With<Music>>,
+) {
+    for (entity, handle) in query.iter() {
+        if asset_server.load_state(handle) != LoadState::Failed {
+            continue;
+        }
+        if let Some(path) = handle.path() {
+            warn!("could not load {}, playing without music", path);
+            missing.0.insert(path.to_string());
+        }
+        commands.entity(entity).despawn();
+    }
+}
+
+// Fades run on real time so they finish while the game is paused, and
+// tracks follow the virtual clock's speed while replays fast-forward.
+// Without `SoundPlugin` tracks play at full volume.
+fn music_fade(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    virtual_time: Res<Time<Virtual>>,
+    settings: Option<Res<AudioSettings>>,
+    mut query: Query<(Entity, &mut Music, &AudioSink)>,
+) {
+    let mixer = settings.map_or(1.0, |settings| settings.music_volume());
+    let step = MUSIC_FADE_SPEED * time.delta_seconds();
+    for (entity, mut music, sink) in query.iter_mut() {
+        music.volume = if music.volume < music.target {
+            (music.volume + step).min(music.target)
+        } else {
+            (music.volume - step).max(music.target)
+        };
+        sink.set_volume(music.volume * mixer);
+        sink.set_speed(virtual_time.relative_speed());
+
+        if music.target == 0.0 && music.volume == 0.0 {
+            sink.stop();
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+fn music_pause(query: Query<&AudioSink, With<Music>>) {
+    for sink in query.iter() {
+        sink.pause();
+    }
+}
+
+fn music_resume(query: Query<&AudioSink, With<Music>>) {
+    for sink in query.iter() {
+        sink.play();
+    }
+}
+
+fn music_stop(mut query: Query<&mut Music>) {
+    for mut music in query.iter_mut() {
+        music.target = 0.0;
+    }
+}
@@ -0,0 +1,235 @@
+use crate::sim::RotationEvent;
+use bevy::asset::io::Reader;
+use bevy::asset::{
+    AssetLoader, AsyncReadExt, LoadContext, LoadState, LoadedFolder, RecursiveDependencyLoadState,
+};
+use bevy::prelude::*;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+use thiserror::Error;
+
+const PATTERN_FOLDER: &str = "patterns";
+const DEFAULT_ROW_SPACING: f32 = 0.0;
+
+// Used until the pattern folder has finished loading, or when it is empty.
+const BUILTIN_PATTERNS: [[u32; 3]; 10] = [
+    [0, 2, 4],
+    [1, 3, 5],
+    [0, 1, 2],
+    [3, 4, 5],
+    [0, 0, 0],
+    [1, 1, 1],
+    [2, 2, 2],
+    [3, 3, 3],
+    [4, 4, 4],
+    [5, 5, 5],
+];
+
+// Sides are relative to the arena's side count when the pattern spawns:
+// indices wrap around it, `gaps` leaves exactly those sides open whatever the
+// count, and `every` repeats each listed side that many sides apart.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PatternRow {
+    #[serde(default)]
+    pub sides: Vec<u32>,
+    #[serde(default)]
+    pub gaps: Option<Vec<u32>>,
+    #[serde(default)]
+    pub every: Option<u32>,
+    // radial gap in pixels between this row and the previous one
+    #[serde(default = "default_row_spacing")]
+    pub spacing: f32,
+    #[serde(default)]
+    pub thickness: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct WallPattern {
+    pub name: String,
+    pub rows: Vec<PatternRow>,
+    // applied to the arena's spin when the pattern spawns
+    #[serde(default)]
+    pub rotation: Option<RotationEvent>,
+}
+
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct PatternSet {
+    pub patterns: Vec<WallPattern>,
+}
+
+// The patterns the wall spawner picks from. `PatternPlugin` keeps it in sync
+// with the pattern folder; without it the builtin table is used.
+#[derive(Resource)]
+pub struct PatternLibrary {
+    folder: Handle<LoadedFolder>,
+    loading: bool,
+    patterns: Vec<WallPattern>,
+}
+
+#[derive(Default)]
+struct PatternSetLoader;
+
+#[derive(Debug, Error)]
+enum PatternSetLoaderError {
+    #[error("could not read pattern file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse pattern file: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+}
+
+pub struct PatternPlugin;
+
+impl Plugin for PatternPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<PatternSet>()
+            .init_asset_loader::<PatternSetLoader>()
+            .init_resource::<PatternLibrary>()
+            .add_systems(Startup, load_patterns)
+            .add_systems(Update, sync_pattern_library);
+    }
+}
+
+impl AssetLoader for PatternSetLoader {
+    type Asset = PatternSet;
+    type Settings = ();
+    type Error = PatternSetLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<PatternSet, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(ron::de::from_bytes::<PatternSet>(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["patterns.ron"]
+    }
+}
+
+impl WallPattern {
+    fn builtin(index: usize, sides: [u32; 3]) -> WallPattern {
+        WallPattern {
+            name: format!("builtin-{}", index),
+            rows: vec![PatternRow {
+                sides: sides.to_vec(),
+                spacing: DEFAULT_ROW_SPACING,
+                ..default()
+            }],
+            rotation: None,
+        }
+    }
+
+    // Radial distance in pixels from the first row to the last one.
+    pub fn depth(&self) -> f32 {
+        self.rows.iter().skip(1).map(|row| row.spacing).sum()
+    }
+}
+
+impl PatternRow {
+    // The sides of an arena with `side_count` sides that get a wall.
+    pub fn resolve_sides(&self, side_count: u32) -> Vec<u32> {
+        let mut sides: Vec<u32> = match (&self.gaps, self.every) {
+            (Some(gaps), _) => {
+                let gaps: Vec<u32> = gaps.iter().map(|side| side % side_count).collect();
+                (0..side_count).filter(|side| !gaps.contains(side)).collect()
+            }
+            (None, Some(every)) if every > 0 => self
+                .sides
+                .iter()
+                .flat_map(|start| (start % side_count..side_count).step_by(every as usize))
+                .collect(),
+            (None, _) => self.sides.iter().map(|side| side % side_count).collect(),
+        };
+        sides.sort();
+        sides.dedup();
+        sides
+    }
+}
+
+impl Default for PatternLibrary {
+    fn default() -> Self {
+        PatternLibrary::new(
+            BUILTIN_PATTERNS
+                .iter()
+                .enumerate()
+                .map(|(index, sides)| WallPattern::builtin(index, *sides))
+                .collect(),
+        )
+    }
+}
+
+impl PatternLibrary {
+    pub fn new(patterns: Vec<WallPattern>) -> PatternLibrary {
+        PatternLibrary {
+            folder: Handle::default(),
+            loading: false,
+            patterns,
+        }
+    }
+
+    pub fn patterns(&self) -> &[WallPattern] {
+        &self.patterns
+    }
+
+    // True until the pattern folder has finished loading, or failed to.
+    pub fn is_loading(&self) -> bool {
+        self.loading
+    }
+}
+
+fn default_row_spacing() -> f32 {
+    DEFAULT_ROW_SPACING
+}
+
+fn load_patterns(mut library: ResMut<PatternLibrary>, asset_server: Res<AssetServer>) {
+    library.folder = asset_server.load_folder(PATTERN_FOLDER);
+    library.loading = true;
+}
+
+pub(crate) fn folder_loading(asset_server: &AssetServer, folder: &Handle<LoadedFolder>) -> bool {
+    match asset_server.get_load_state(folder) {
+        None | Some(LoadState::Failed) => false,
+        Some(_) => !matches!(
+            asset_server.get_recursive_dependency_load_state(folder),
+            Some(RecursiveDependencyLoadState::Loaded | RecursiveDependencyLoadState::Failed)
+        ),
+    }
+}
+
+// Rebuilds the library whenever a pattern file is added, edited or removed,
+// and once the folder has finished loading, sorted by name so the order does
+// not depend on load order. Falls back to the builtin table while nothing is
+// loaded.
+fn sync_pattern_library(
+    mut events: EventReader<AssetEvent<PatternSet>>,
+    sets: Res<Assets<PatternSet>>,
+    asset_server: Res<AssetServer>,
+    mut library: ResMut<PatternLibrary>,
+) {
+    let loading = library.loading && folder_loading(&asset_server, &library.folder);
+    if events.read().count() == 0 && loading == library.loading {
+        return;
+    }
+
+    let mut patterns: Vec<WallPattern> = sets
+        .iter()
+        .flat_map(|(_, set)| set.patterns.iter().cloned())
+        .filter(|pattern| !pattern.rows.is_empty())
+        .collect();
+    patterns.sort_by(|a, b| a.name.cmp(&b.name));
+
+    if patterns.is_empty() {
+        let folder = library.folder.clone();
+        *library = PatternLibrary { folder, ..default() };
+    } else {
+        info!("loaded {} wall patterns", patterns.len());
+        library.patterns = patterns;
+    }
+    library.loading = loading;
+}
@@ -0,0 +1,163 @@
+use crate::controls::{Action, ActionState};
+use crate::game::ActiveRun;
+use crate::GameState;
+use bevy::prelude::*;
+
+const PAUSE_ITEMS: [&str; 3] = ["Resume", "Restart", "Quit to Menu"];
+const PAUSE_ITEM_COLOR: Color = Color::BLUE;
+const PAUSE_HOVER_COLOR: Color = Color::WHITE;
+
+#[derive(Component)]
+struct OnPauseScreen;
+
+#[derive(Component)]
+struct PauseItem {
+    index: usize,
+}
+
+#[derive(Resource, Default)]
+struct PauseMenu {
+    hover: usize,
+}
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PauseMenu>()
+            .add_systems(
+                Update,
+                pause_toggle.run_if(in_state(GameState::Playing)),
+            )
+            .add_systems(OnEnter(GameState::Paused), (pause_time, pause_setup))
+            .add_systems(
+                Update,
+                (pause_input, pause_update)
+                    .chain()
+                    .run_if(in_state(GameState::Paused)),
+            )
+            .add_systems(OnExit(GameState::Paused), (resume_time, pause_cleanup));
+    }
+}
+
+fn pause_toggle(actions: Res<ActionState>, mut game_state: ResMut<NextState<GameState>>) {
+    if actions.just_pressed(Action::Pause) {
+        info!("pausing game");
+        game_state.set(GameState::Paused);
+    }
+}
+
+fn pause_time(mut time: ResMut<Time<Virtual>>) {
+    time.pause();
+}
+
+fn resume_time(mut time: ResMut<Time<Virtual>>) {
+    time.unpause();
+}
+
+fn pause_setup(mut commands: Commands, mut menu: ResMut<PauseMenu>) {
+    menu.hover = 0;
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                background_color: Color::rgba(0.0, 0.0, 0.0, 0.6).into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            OnPauseScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    "Paused",
+                    TextStyle {
+                        font_size: 100.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                )
+                .with_style(Style {
+                    margin: UiRect::all(Val::Px(50.0)),
+                    ..default()
+                }),
+            );
+
+            for (index, label) in PAUSE_ITEMS.iter().enumerate() {
+                parent.spawn((
+                    TextBundle::from_section(
+                        *label,
+                        TextStyle {
+                            font_size: 50.0,
+                            color: PAUSE_ITEM_COLOR,
+                            ..default()
+                        },
+                    )
+                    .with_style(Style {
+                        margin: UiRect::all(Val::Px(10.0)),
+                        ..default()
+                    }),
+                    PauseItem { index },
+                ));
+            }
+        });
+}
+
+fn pause_input(
+    mut commands: Commands,
+    mut menu: ResMut<PauseMenu>,
+    actions: Res<ActionState>,
+    mut game_state: ResMut<NextState<GameState>>,
+) {
+    if actions.any_just_pressed([Action::Pause, Action::Back]) {
+        info!("resuming game");
+        game_state.set(GameState::Playing);
+        return;
+    }
+
+    if actions.just_pressed(Action::Up) {
+        menu.hover = (menu.hover + PAUSE_ITEMS.len() - 1) % PAUSE_ITEMS.len();
+    }
+    if actions.just_pressed(Action::Down) {
+        menu.hover = (menu.hover + 1) % PAUSE_ITEMS.len();
+    }
+    if actions.just_pressed(Action::Confirm) {
+        match menu.hover {
+            0 => game_state.set(GameState::Playing),
+            1 => {
+                // Dropping the run makes re-entering Playing set up a fresh one.
+                commands.remove_resource::<ActiveRun>();
+                game_state.set(GameState::Playing);
+            }
+            2 => game_state.set(GameState::Menu),
+            _ => {}
+        }
+    }
+}
+
+fn pause_update(menu: Res<PauseMenu>, mut query: Query<(&mut Text, &PauseItem)>) {
+    for (mut text, item) in query.iter_mut() {
+        let color = if item.index == menu.hover {
+            PAUSE_HOVER_COLOR
+        } else {
+            PAUSE_ITEM_COLOR
+        };
+        for section in text.sections.iter_mut() {
+            section.style.color = color;
+        }
+    }
+}
+
+fn pause_cleanup(query: Query<Entity, With<OnPauseScreen>>, mut commands: Commands) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
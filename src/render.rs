@@ -0,0 +1,549 @@
+use crate::game::{ActiveRun, MilestoneReached, OnGameScreen, PlayerHit, PreviousTick};
+use crate::levels::CurrentLevel;
+use crate::music::BeatClock;
+use crate::settings::Settings;
+use crate::sim::{Arena, Wall, MAX_SIDES};
+use crate::theme::Theme;
+use crate::{Game, GameState};
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+
+const CENTER_HEX_HEIGHT: f32 = 10.0;
+const BORDER_WIDTH: f32 = 3000.0;
+const BORDER_HEIGHT: f32 = 10.0;
+const BACKGROUD_MOVE_SPEED: f32 = 0.5;
+const ANNOUNCEMENT_SECONDS: f32 = 2.0;
+const HIT_FLASH_SECONDS: f32 = 0.4;
+const BEAT_FLASH: f32 = 0.25;          // how far the background brightens towards white on a beat
+
+#[derive(Component)]
+struct PlayerSprite;
+
+// Names the milestone just reached, fading out over `ANNOUNCEMENT_SECONDS`.
+#[derive(Component)]
+struct Announcement(Timer);
+
+// Covers the screen, game over screen included, when a wall hits the player
+// and fades out over `HIT_FLASH_SECONDS`.
+#[derive(Component)]
+struct HitFlash(Timer);
+
+// Slices, borders and center sides are spawned for the largest arena and
+// fitted to the current one every frame; sides it does not have collapse to
+// nothing.
+#[derive(Component)]
+struct BackgroundSlice {
+    index: u32,
+}
+
+// The center polygon, drawn as a filled polygon inside an outline.
+#[derive(Component)]
+enum CenterHex {
+    Fill,
+    Outline,
+}
+
+// Which of the theme's colors something is drawn in.
+#[derive(Component, Clone, Copy)]
+pub enum Themed {
+    Walls,
+    Center,
+    Player,
+    Hud,
+}
+
+// Shared by every wall of the run.
+#[derive(Resource)]
+struct WallMaterial(Handle<ColorMaterial>);
+
+#[derive(Component)]
+struct Borders {
+    index: u32,
+}
+
+// Projects the simulation in `GamePlugin` onto sprites and meshes. Leaving
+// this plugin out runs the game headless.
+pub struct GameRenderPlugin;
+
+impl Plugin for GameRenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                (
+                    render_setup,
+                    spawn_background_slices,
+                    spawn_background_borders,
+                    spawn_center_hex,
+                )
+                    .chain()
+                    .run_if(resource_added::<ActiveRun>),
+                attach_wall_meshes,
+                game_theme.run_if(in_state(GameState::Playing)),
+                (
+                    game_player_render,
+                    game_announcement,
+                    game_wall_render,
+                    game_background_mover,
+                    game_border_mover,
+                    game_center_hex_mover,
+                )
+                    .run_if(in_state(GameState::Playing)),
+            )
+                .chain(),
+        )
+        .add_systems(Update, (hit_flash_spawn, hit_flash_fade));
+    }
+}
+
+fn render_setup(
+    mut commands: Commands,
+    level: Res<CurrentLevel>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    height: Val::Percent(100.0),
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            OnGameScreen,
+        ))
+        .with_children(|parent| {
+            let mut timer = Timer::from_seconds(ANNOUNCEMENT_SECONDS, TimerMode::Once);
+            timer.tick(timer.duration());
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 80.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+                Announcement(timer),
+                Themed::Hud,
+                OnGameScreen,
+            ));
+        });
+
+    let triangle = Triangle2d {
+        vertices: [
+            Vec2::Y * 0.25,
+            Vec2::new(-0.25, -0.25),
+            Vec2::new(0.25, -0.25),
+        ],
+    };
+    commands.spawn((
+        MaterialMesh2dBundle {
+            mesh: meshes
+                .add(Mesh::from(triangle))
+                .into(),
+            material: materials.add(level.0.palette.player()),
+            transform: Transform {
+                translation: Vec3::new(0.0, 0.0, 3.0),
+                scale: Vec3::new(60.0, 60.0, 2.0),
+                ..default()
+            },
+            ..default()
+        },
+        OnGameScreen,
+        PlayerSprite,
+        Themed::Player,
+    ));
+
+    commands.insert_resource(WallMaterial(materials.add(level.0.palette.walls())));
+    commands.insert_resource(Theme::new(&level.0.palette, &level.0.theme));
+}
+
+// A mesh of `quads` quads, with their corners filled in by `set_quads`.
+fn quad_mesh(quads: usize) -> Mesh {
+    let indices = (0..quads as u32)
+        .flat_map(|quad| [0, 1, 2, 0, 2, 3].map(|corner| quad * 4 + corner))
+        .collect();
+    Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default())
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, vec![[0.0; 3]; quads * 4])
+        .with_inserted_indices(Indices::U32(indices))
+}
+
+fn set_quads(mesh: &mut Mesh, quads: impl IntoIterator<Item = [Vec2; 4]>) {
+    let positions: Vec<[f32; 3]> = quads
+        .into_iter()
+        .flatten()
+        .map(|corner| [corner.x, corner.y, 0.0])
+        .collect();
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+}
+
+// Each wall gets a mesh of its own, reshaped every frame.
+fn attach_wall_meshes(
+    mut commands: Commands,
+    material: Option<Res<WallMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    query: Query<Entity, Added<Wall>>,
+) {
+    let Some(material) = material else {
+        return;
+    };
+    for entity in query.iter() {
+        commands.entity(entity).insert(MaterialMesh2dBundle {
+            mesh: meshes.add(quad_mesh(1)).into(),
+            material: material.0.clone(),
+            transform: Transform::from_xyz(0.0, 0.0, 10.0),
+            ..default()
+        });
+    }
+}
+
+fn game_wall_render(
+    game: Res<Game>,
+    previous: Res<PreviousTick>,
+    fixed_time: Res<Time<Fixed>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    query: Query<(&Wall, &Mesh2dHandle)>,
+) {
+    let alpha = fixed_time.overstep_fraction();
+    let theta = interpolated_theta(&game, &previous, &fixed_time);
+    let arena = interpolated_arena(&game, &previous, &fixed_time);
+
+    for (wall, mesh) in query.iter() {
+        let distance = wall.prev_distance.lerp(wall.distance, alpha);
+        if let Some(mesh) = meshes.get_mut(&mesh.0) {
+            set_quads(
+                mesh,
+                [arena.band(
+                    theta,
+                    wall.side,
+                    distance - wall.thickness / 2.0,
+                    distance + wall.thickness / 2.0,
+                )],
+            );
+        }
+    }
+}
+
+// Interpolates along the shorter way around, since angles wrap at 360.
+fn lerp_angle(from: f32, to: f32, alpha: f32) -> f32 {
+    let delta = (to - from + 180.0).rem_euclid(360.0) - 180.0;
+    (from + delta * alpha).rem_euclid(360.0)
+}
+
+fn interpolated_theta(game: &Game, previous: &PreviousTick, fixed_time: &Time<Fixed>) -> f32 {
+    lerp_angle(previous.theta, game.theta, fixed_time.overstep_fraction())
+}
+
+fn interpolated_arena(game: &Game, previous: &PreviousTick, fixed_time: &Time<Fixed>) -> Arena {
+    previous.arena.lerp(&game.arena, fixed_time.overstep_fraction())
+}
+
+fn game_player_render(
+    game: Res<Game>,
+    previous: Res<PreviousTick>,
+    fixed_time: Res<Time<Fixed>>,
+    mut query: Query<&mut Transform, (With<OnGameScreen>, With<PlayerSprite>)>,
+) {
+    let alpha = fixed_time.overstep_fraction();
+    let theta = lerp_angle(previous.player_theta, game.player.theta, alpha);
+    let radius = previous.player_radius.lerp(game.player_radius, alpha);
+
+    for mut transform in query.iter_mut() {
+        transform.translation.x = theta.to_radians().cos() * radius;
+        transform.translation.y = theta.to_radians().sin() * radius;
+        transform.rotation = Quat::from_rotation_z(theta.to_radians() + 270.0_f32.to_radians());
+    }
+}
+
+fn game_announcement(
+    time: Res<Time>,
+    mut milestones: EventReader<MilestoneReached>,
+    mut query: Query<(&mut Text, &mut Announcement)>,
+) {
+    let reached = milestones.read().last();
+    for (mut text, mut announcement) in query.iter_mut() {
+        if let Some(milestone) = reached {
+            text.sections[0].value = milestone.name.clone();
+            announcement.0.reset();
+        }
+        announcement.0.tick(time.delta());
+        text.sections[0]
+            .style
+            .color
+            .set_a(announcement.0.fraction_remaining());
+    }
+}
+
+// Moves the theme along and recolors everything drawn in it but the
+// background, which `game_background_mover` recolors along with the beat.
+// Materials are updated in place. Reduced motion skips the stage flashes.
+#[allow(clippy::too_many_arguments)]
+fn game_theme(
+    time: Res<Time>,
+    level: Res<CurrentLevel>,
+    settings: Res<Settings>,
+    wall_material: Res<WallMaterial>,
+    mut milestones: EventReader<MilestoneReached>,
+    mut theme: ResMut<Theme>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    shapes: Query<(&Themed, &Handle<ColorMaterial>)>,
+    mut sprites: Query<(&Themed, &mut Sprite)>,
+    mut texts: Query<(&Themed, &mut Text)>,
+    mut nodes: Query<(&Themed, &mut BackgroundColor)>,
+) {
+    let style = &level.0.theme;
+    if milestones.read().count() > 0 && !settings.reduced_motion {
+        theme.new_stage(style);
+    }
+    theme.advance(time.delta_seconds(), &level.0.palette, style);
+    let colors = *theme.colors();
+
+    let color = |themed: &Themed| match themed {
+        Themed::Walls => colors.walls,
+        Themed::Center => colors.center,
+        Themed::Player => colors.player,
+        Themed::Hud => colors.hud,
+    };
+
+    if let Some(material) = materials.get_mut(&wall_material.0) {
+        material.color = colors.walls;
+    }
+    for (themed, material) in shapes.iter() {
+        if let Some(material) = materials.get_mut(material) {
+            material.color = color(themed);
+        }
+    }
+    for (themed, mut sprite) in sprites.iter_mut() {
+        sprite.color = color(themed);
+    }
+    // Text and UI nodes keep their own alpha, which announcements fade.
+    for (themed, mut text) in texts.iter_mut() {
+        for section in text.sections.iter_mut() {
+            let alpha = section.style.color.a();
+            section.style.color = color(themed).with_a(alpha);
+        }
+    }
+    for (themed, mut background) in nodes.iter_mut() {
+        let alpha = background.0.a();
+        background.0 = color(themed).with_a(alpha);
+    }
+}
+
+fn hit_flash_spawn(
+    mut commands: Commands,
+    mut hits: EventReader<PlayerHit>,
+    settings: Res<Settings>,
+) {
+    if hits.read().count() == 0 || settings.reduced_motion {
+        return;
+    }
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            background_color: Color::WHITE.into(),
+            z_index: ZIndex::Global(100),
+            ..default()
+        },
+        HitFlash(Timer::from_seconds(HIT_FLASH_SECONDS, TimerMode::Once)),
+    ));
+}
+
+fn hit_flash_fade(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    mut query: Query<(Entity, &mut HitFlash, &mut BackgroundColor)>,
+) {
+    for (entity, mut flash, mut color) in query.iter_mut() {
+        if flash.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+        } else {
+            color.0.set_a(flash.0.fraction_remaining());
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn game_background_mover(
+    game: Res<Game>,
+    previous: Res<PreviousTick>,
+    fixed_time: Res<Time<Fixed>>,
+    theme: Res<Theme>,
+    clock: Res<BeatClock>,
+    settings: Res<Settings>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut query: Query<(&mut Transform, &BackgroundSlice, &Handle<ColorMaterial>)>,
+) {
+    let theta = interpolated_theta(&game, &previous, &fixed_time);
+    let arena = interpolated_arena(&game, &previous, &fixed_time);
+    let flash = if settings.reduced_motion {
+        0.0
+    } else {
+        BEAT_FLASH * clock.pulse()
+    };
+    for (mut transform, slice, material) in query.iter_mut() {
+        if let Some(material) = materials.get_mut(material) {
+            let [r, g, b, _] = theme.colors().background(slice.index).as_rgba_f32();
+            material.color = Color::rgb(
+                r + (1.0 - r) * flash,
+                g + (1.0 - g) * flash,
+                b + (1.0 - b) * flash,
+            );
+        }
+
+        // The slice mesh is a unit wedge; scaling it opens it to the side's
+        // angular width.
+        let (start, width) = arena.sector(theta, slice.index);
+        let half_width = (width / 2.0).to_radians();
+        transform.rotation = Quat::from_rotation_z((start + width / 2.0).to_radians());
+        transform.scale.x = half_width.cos() * BORDER_WIDTH;
+        transform.scale.y = half_width.sin() * BORDER_WIDTH;
+    }
+}
+
+fn game_border_mover(
+    game: Res<Game>,
+    previous: Res<PreviousTick>,
+    fixed_time: Res<Time<Fixed>>,
+    mut query: Query<(&mut Transform, &Borders)>,
+) {
+    let theta = interpolated_theta(&game, &previous, &fixed_time);
+    let arena = interpolated_arena(&game, &previous, &fixed_time);
+    for (mut transform, border) in query.iter_mut() {
+        let (start, _) = arena.sector(theta, border.index);
+        let start = start.to_radians();
+        transform.rotation = Quat::from_rotation_z(start);
+        transform.translation.x = start.cos() * BORDER_WIDTH / 2.0;
+        transform.translation.y = start.sin() * BORDER_WIDTH / 2.0;
+        transform.scale.x = if (border.index as f32) < arena.sides {
+            BORDER_WIDTH
+        } else {
+            0.0
+        };
+    }
+}
+
+fn spawn_background_slices(
+    mut commands: Commands,
+    level: Res<CurrentLevel>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let wedge = meshes.add(Mesh::from(Triangle2d {
+        vertices: [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, -1.0),
+            Vec2::new(1.0, 1.0),
+        ],
+    }));
+
+    for i in 0..MAX_SIDES {
+        let translation = Vec3::new(0.0, 0.0, 1.0);
+        let color = level.0.palette.background(i);
+
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: wedge.clone().into(),
+                material: materials.add(color),
+                transform: Transform {
+                    translation,
+                    scale: Vec3::ZERO,
+                    ..default()
+                },
+                ..default()
+            },
+            OnGameScreen,
+            BackgroundSlice { index: i },
+        ));
+    }
+}
+
+fn spawn_background_borders(mut commands: Commands, level: Res<CurrentLevel>) {
+    for i in 0..MAX_SIDES {
+        let scale = Vec3::new(0.0, BORDER_HEIGHT, 2.0);
+        let translation = Vec3::new(0.0, 0.0, 2.0);
+
+        commands.spawn((
+            SpriteBundle {
+                transform: Transform {
+                    translation,
+                    scale,
+                    ..default()
+                },
+                sprite: Sprite {
+                    color: level.0.palette.walls(),
+                    ..default()
+                },
+                ..default()
+            },
+            OnGameScreen,
+            Borders { index: i },
+            Themed::Walls,
+        ));
+    }
+}
+
+fn game_center_hex_mover(
+    game: Res<Game>,
+    previous: Res<PreviousTick>,
+    fixed_time: Res<Time<Fixed>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    query: Query<(&CenterHex, &Mesh2dHandle)>,
+) {
+    let theta = interpolated_theta(&game, &previous, &fixed_time);
+    let arena = interpolated_arena(&game, &previous, &fixed_time);
+    let radius = previous
+        .center_ring_radius
+        .lerp(game.center_ring_radius, fixed_time.overstep_fraction());
+    let half_height = CENTER_HEX_HEIGHT / 2.0;
+
+    for (shape, mesh) in query.iter() {
+        // The fill is a band from the very center, so each side is a wedge.
+        let (inner, outer) = match shape {
+            CenterHex::Fill => (0.0, radius - half_height),
+            CenterHex::Outline => (radius - half_height, radius + half_height),
+        };
+        if let Some(mesh) = meshes.get_mut(&mesh.0) {
+            set_quads(
+                mesh,
+                (0..MAX_SIDES).map(|side| arena.band(theta, side, inner, outer)),
+            );
+        }
+    }
+}
+
+fn spawn_center_hex(
+    mut commands: Commands,
+    level: Res<CurrentLevel>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let palette = &level.0.palette;
+    for (shape, themed, color, z) in [
+        (CenterHex::Fill, Themed::Center, palette.center(), 2.2),
+        (CenterHex::Outline, Themed::Walls, palette.walls(), 2.4),
+    ] {
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: meshes.add(quad_mesh(MAX_SIDES as usize)).into(),
+                material: materials.add(color),
+                transform: Transform::from_xyz(0.0, 0.0, z),
+                ..default()
+            },
+            OnGameScreen,
+            shape,
+            themed,
+        ));
+    }
+}
@@ -0,0 +1,477 @@
+use crate::controls::{Action, ActionState};
+use crate::game::{run_in_progress, ActiveRun, PlayerInput, RestartRun, TickSet};
+use crate::levels::{CurrentLevel, LevelLibrary, SelectedLevel};
+use crate::patterns::PatternLibrary;
+use crate::seed::{GameRng, SeedMode};
+use crate::GameState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+const REPLAY_VERSION: u32 = 4;
+const REPLAY_DIR_NAME: &str = "replays";
+const PLAYBACK_SPEEDS: [f64; 4] = [1.0, 2.0, 4.0, 8.0];
+const SEEK_SPEED: f64 = 32.0;
+// Lets fast playback run many ticks per frame instead of being clamped to
+// Bevy's default of 250 ms of game time per frame.
+const PLAYBACK_MAX_DELTA: Duration = Duration::from_secs(1);
+const SCRUB_STEP_SECONDS: f64 = 5.0;
+const SCRUB_BAR_HEIGHT: f32 = 16.0;
+
+// A whole run: its seed, level and the input of every fixed tick. Levels and
+// walls come from the level and pattern files, so a replay only reproduces
+// the run against the same files.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Replay {
+    version: u32,
+    pub seed: u64,
+    pub tick_rate: f64,
+    pub level: String,
+    // run-length encoded: each entry is an input and for how many ticks it
+    // was held
+    inputs: Vec<(PlayerInput, u32)>,
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("could not access replay file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse replay file: {0}")]
+    Parse(#[from] ron::error::SpannedError),
+    #[error("could not write replay file: {0}")]
+    Write(#[from] ron::Error),
+    #[error("unsupported replay version {0}")]
+    Version(u32),
+}
+
+// The run in progress, or the last finished one.
+#[derive(Resource, Default)]
+pub struct ReplayRecording(pub Option<Replay>);
+
+// Present while a replay is played back instead of reading the keyboard.
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub replay: Replay,
+    // ticks of the replay consumed so far in the current run
+    pub tick: u32,
+    // tick the playback is fast-forwarding to
+    pub seek: Option<u32>,
+    speed: usize,
+}
+
+#[derive(Resource)]
+struct ReplayDir(PathBuf);
+
+#[derive(Component)]
+struct OnReplayControls;
+
+#[derive(Component)]
+struct ScrubBar;
+
+#[derive(Component)]
+struct ScrubBarFill;
+
+#[derive(Component)]
+struct ReplayStatus;
+
+// Records every run and, given a replay, feeds it back into the simulation.
+#[derive(Default)]
+pub struct ReplayPlugin {
+    pub playback: Option<Replay>,
+    // where finished runs are saved; `None` only keeps the last one in memory
+    pub save_dir: Option<PathBuf>,
+}
+
+// Playback speed and scrub bar; leave it out to play replays back headless.
+pub struct ReplayControlsPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayRecording>()
+            .add_systems(
+                FixedUpdate,
+                replay_record
+                    .in_set(TickSet::Simulate)
+                    .run_if(not(resource_exists::<ReplayPlayback>)),
+            )
+            .add_systems(
+                FixedUpdate,
+                replay_feed
+                    .after(TickSet::Input)
+                    .before(TickSet::Simulate)
+                    .run_if(
+                        in_state(GameState::Playing)
+                            .and_then(run_in_progress)
+                            .and_then(resource_exists::<ReplayPlayback>),
+                    ),
+            )
+            .add_systems(
+                OnEnter(GameState::GameOver),
+                replay_save.run_if(
+                    resource_exists::<ReplayDir>.and_then(not(resource_exists::<ReplayPlayback>)),
+                ),
+            );
+
+        if let Some(dir) = &self.save_dir {
+            app.insert_resource(ReplayDir(dir.clone()));
+        }
+        if let Some(replay) = &self.playback {
+            app.insert_resource(ReplayPlayback::new(replay.clone()))
+                .add_systems(Startup, replay_setup_playback)
+                .add_systems(Update, replay_start_playback);
+        }
+    }
+}
+
+impl Plugin for ReplayControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (
+                replay_controls_setup.run_if(resource_added::<ReplayPlayback>),
+                replay_controls_input.run_if(in_state(GameState::Playing)),
+                replay_time_scale,
+                replay_controls_update,
+            )
+                .chain()
+                .run_if(resource_exists::<ReplayPlayback>),
+        );
+    }
+}
+
+pub fn replay_dir() -> PathBuf {
+    crate::highscore::config_dir().join(REPLAY_DIR_NAME)
+}
+
+impl Replay {
+    pub fn new(seed: u64, tick_rate: f64, level: &str) -> Replay {
+        Replay {
+            version: REPLAY_VERSION,
+            seed,
+            tick_rate,
+            level: level.to_string(),
+            inputs: Vec::new(),
+        }
+    }
+
+    // Understands `--replay <path>`; anything else is left for other parsers.
+    // Call it once logging is set up, since bad arguments are only warned
+    // about.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Option<Replay> {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if arg != "--replay" {
+                continue;
+            }
+            let Some(path) = args.next() else {
+                warn!("--replay expects a replay file");
+                return None;
+            };
+            return match Replay::load(Path::new(&path)) {
+                Ok(replay) => Some(replay),
+                Err(err) => {
+                    warn!("could not load replay {}: {}", path, err);
+                    None
+                }
+            };
+        }
+        None
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, ReplayError> {
+        let replay = ron::from_str::<Replay>(&fs::read_to_string(path)?)?;
+        if replay.version != REPLAY_VERSION {
+            return Err(ReplayError::Version(replay.version));
+        }
+        Ok(replay)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ReplayError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, ron::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn push(&mut self, input: PlayerInput) {
+        match self.inputs.last_mut() {
+            Some((last, count)) if *last == input => *count += 1,
+            _ => self.inputs.push((input, 1)),
+        }
+    }
+
+    pub fn ticks(&self) -> u32 {
+        self.inputs.iter().map(|(_, count)| count).sum()
+    }
+
+    // The input of `tick`; past the end of the replay nothing is pressed.
+    pub fn input_at(&self, tick: u32) -> PlayerInput {
+        let mut start = 0;
+        for (input, count) in self.inputs.iter() {
+            start += count;
+            if tick < start {
+                return *input;
+            }
+        }
+        PlayerInput::default()
+    }
+
+    pub fn seconds(&self, tick: u32) -> f64 {
+        tick as f64 / self.tick_rate
+    }
+}
+
+impl ReplayPlayback {
+    pub fn new(replay: Replay) -> ReplayPlayback {
+        ReplayPlayback {
+            replay,
+            tick: 0,
+            seek: None,
+            speed: 0,
+        }
+    }
+
+    pub fn speed(&self) -> f64 {
+        PLAYBACK_SPEEDS[self.speed]
+    }
+
+    // Returns true when `tick` lies behind the current one, so the run has to
+    // restart and fast-forward from the beginning.
+    pub fn seek_to(&mut self, tick: u32) -> bool {
+        let tick = tick.min(self.replay.ticks());
+        self.seek = Some(tick);
+        tick < self.tick
+    }
+}
+
+// Part of `TickSet::Simulate`, so it stops on the tick the run ends along
+// with the simulation, even if the frame has ticks left.
+fn replay_record(
+    run: Res<ActiveRun>,
+    rng: Res<GameRng>,
+    level: Res<CurrentLevel>,
+    time: Res<Time<Fixed>>,
+    input: Res<PlayerInput>,
+    mut recording: ResMut<ReplayRecording>,
+) {
+    if run.is_added() || recording.0.is_none() {
+        let tick_rate = 1.0 / time.timestep().as_secs_f64();
+        recording.0 = Some(Replay::new(rng.seed, tick_rate, &level.0.name));
+    }
+
+    if let Some(replay) = recording.0.as_mut() {
+        replay.push(*input);
+    }
+}
+
+fn replay_save(recording: Res<ReplayRecording>, dir: Res<ReplayDir>) {
+    let Some(replay) = &recording.0 else {
+        return;
+    };
+
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let path = dir.0.join(format!("{}-{}.ron", started, replay.seed));
+    match replay.save(&path) {
+        Ok(()) => info!("saved replay to {}", path.display()),
+        Err(err) => warn!("could not save replay to {}: {}", path.display(), err),
+    }
+}
+
+fn replay_setup_playback(
+    playback: Res<ReplayPlayback>,
+    mut seed_mode: ResMut<SeedMode>,
+    mut selected_level: ResMut<SelectedLevel>,
+    mut time: ResMut<Time<Fixed>>,
+) {
+    info!(
+        "playing back replay of {} with seed {} ({:.2} s)",
+        playback.replay.level,
+        playback.replay.seed,
+        playback.replay.seconds(playback.replay.ticks())
+    );
+    *seed_mode = SeedMode::Fixed(playback.replay.seed);
+    selected_level.0 = playback.replay.level.clone();
+    time.set_timestep_hz(playback.replay.tick_rate);
+}
+
+// Starts the run once levels and patterns have loaded, since a run on the
+// fallback ones would not match the recording.
+fn replay_start_playback(
+    levels: Res<LevelLibrary>,
+    patterns: Res<PatternLibrary>,
+    game_state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut started: Local<bool>,
+) {
+    if *started || levels.is_loading() || patterns.is_loading() {
+        return;
+    }
+
+    *started = true;
+    if *game_state.get() != GameState::Playing {
+        next_state.set(GameState::Playing);
+    }
+}
+
+fn replay_feed(
+    run: Res<ActiveRun>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut input: ResMut<PlayerInput>,
+) {
+    if run.is_added() {
+        playback.tick = 0;
+    }
+
+    *input = playback.replay.input_at(playback.tick);
+    playback.tick += 1;
+
+    if playback.seek.is_some_and(|seek| playback.tick >= seek) {
+        playback.seek = None;
+    }
+}
+
+fn replay_controls_setup(mut commands: Commands) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    width: Val::Percent(100.0),
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(0.0),
+                    flex_direction: FlexDirection::Column,
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                z_index: ZIndex::Global(5),
+                ..default()
+            },
+            OnReplayControls,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 24.0,
+                        color: Color::WHITE,
+                        ..default()
+                    },
+                ),
+                ReplayStatus,
+            ));
+
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            width: Val::Percent(100.0),
+                            height: Val::Px(SCRUB_BAR_HEIGHT),
+                            margin: UiRect::top(Val::Px(5.0)),
+                            ..default()
+                        },
+                        background_color: Color::DARK_GRAY.into(),
+                        ..default()
+                    },
+                    Interaction::default(),
+                    ScrubBar,
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Percent(0.0),
+                                height: Val::Percent(100.0),
+                                ..default()
+                            },
+                            background_color: Color::WHITE.into(),
+                            ..default()
+                        },
+                        ScrubBarFill,
+                    ));
+                });
+        });
+}
+
+// Up/Down change the playback speed, rotating left/right jumps by a few
+// seconds and clicking the scrub bar jumps to that point of the run.
+fn replay_controls_input(
+    actions: Res<ActionState>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut playback: ResMut<ReplayPlayback>,
+    mut restart: EventWriter<RestartRun>,
+    windows: Query<&Window>,
+    bars: Query<(&Interaction, &Node, &GlobalTransform), With<ScrubBar>>,
+) {
+    if actions.just_pressed(Action::Up) {
+        playback.speed = (playback.speed + 1).min(PLAYBACK_SPEEDS.len() - 1);
+    }
+    if actions.just_pressed(Action::Down) {
+        playback.speed = playback.speed.saturating_sub(1);
+    }
+
+    let step = (SCRUB_STEP_SECONDS * playback.replay.tick_rate) as u32;
+    let mut target = None;
+    if actions.just_pressed(Action::RotateRight) {
+        target = Some(playback.tick + step);
+    }
+    if actions.just_pressed(Action::RotateLeft) {
+        target = Some(playback.tick.saturating_sub(step));
+    }
+
+    let cursor = windows.iter().find_map(Window::cursor_position);
+    for (interaction, node, transform) in bars.iter() {
+        if *interaction != Interaction::Pressed || !mouse.just_pressed(MouseButton::Left) {
+            continue;
+        }
+        if let Some(cursor) = cursor {
+            let left = transform.translation().x - node.size().x / 2.0;
+            let fraction = ((cursor.x - left) / node.size().x).clamp(0.0, 1.0);
+            target = Some((fraction * playback.replay.ticks() as f32) as u32);
+        }
+    }
+
+    if let Some(target) = target {
+        if playback.seek_to(target) {
+            restart.send(RestartRun);
+        }
+    }
+}
+
+fn replay_time_scale(playback: Res<ReplayPlayback>, mut time: ResMut<Time<Virtual>>) {
+    let speed = match playback.seek {
+        Some(_) => SEEK_SPEED,
+        None => playback.speed(),
+    };
+    if time.relative_speed_f64() != speed {
+        time.set_relative_speed_f64(speed);
+        time.set_max_delta(PLAYBACK_MAX_DELTA);
+    }
+}
+
+fn replay_controls_update(
+    playback: Res<ReplayPlayback>,
+    mut status: Query<&mut Text, With<ReplayStatus>>,
+    mut fill: Query<&mut Style, With<ScrubBarFill>>,
+) {
+    let ticks = playback.replay.ticks().max(1);
+    let tick = playback.tick.min(ticks);
+
+    for mut text in status.iter_mut() {
+        text.sections[0].value = format!(
+            "Replay  {:.2} / {:.2}  {}x",
+            playback.replay.seconds(tick),
+            playback.replay.seconds(ticks),
+            playback.speed()
+        );
+    }
+    for mut style in fill.iter_mut() {
+        style.width = Val::Percent(100.0 * tick as f32 / ticks as f32);
+    }
+}
@@ -0,0 +1,72 @@
+use bevy::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 60 * 60 * 24;
+const DAILY_SEED_SALT: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// How the seed of each new run is chosen.
+#[derive(Resource, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SeedMode {
+    #[default]
+    Random,
+    Fixed(u64),
+    Daily,
+}
+
+// The only source of randomness for gameplay, so that a run is fully
+// determined by its seed. ChaCha8 rather than `StdRng`, whose algorithm may
+// change between rand versions and would break daily seeds and replays.
+#[derive(Resource)]
+pub struct GameRng {
+    pub seed: u64,
+    pub rng: ChaCha8Rng,
+}
+
+impl SeedMode {
+    // Understands `--seed <n>` and `--daily`; anything else is left for
+    // other parsers. Call it once logging is set up, since bad arguments are
+    // only warned about.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> SeedMode {
+        let mut mode = SeedMode::Random;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--daily" => mode = SeedMode::Daily,
+                "--seed" => match args.next().map(|value| value.parse::<u64>()) {
+                    Some(Ok(seed)) => mode = SeedMode::Fixed(seed),
+                    _ => warn!("--seed expects an unsigned integer, using a random seed"),
+                },
+                _ => {}
+            }
+        }
+        mode
+    }
+
+    pub fn next_seed(&self) -> u64 {
+        match self {
+            SeedMode::Random => rand::thread_rng().gen(),
+            SeedMode::Fixed(seed) => *seed,
+            SeedMode::Daily => daily_seed(),
+        }
+    }
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> GameRng {
+        GameRng {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+}
+
+// Everyone playing on the same (UTC) day gets the same seed.
+pub fn daily_seed() -> u64 {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs() / SECONDS_PER_DAY)
+        .unwrap_or_default();
+    days.wrapping_mul(DAILY_SEED_SALT)
+}
@@ -0,0 +1,391 @@
+use crate::levels::{LevelDefinition, Palette};
+use crate::sound::AudioSettings;
+use bevy::prelude::*;
+use bevy::window::{PresentMode, PrimaryWindow, WindowMode};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const SETTINGS_VERSION: u32 = 1;
+pub const SETTINGS_FILE: &str = "settings.ron";
+const RESOLUTIONS: [(u32, u32); 5] = [
+    (1280, 720),
+    (1366, 768),
+    (1600, 900),
+    (1920, 1080),
+    (2560, 1440),
+];
+const MIN_RESOLUTION: (u32, u32) = (640, 360);
+const MAX_RESOLUTION: (u32, u32) = (7680, 4320);
+const VOLUME_STEP: f32 = 0.1;
+const VOLUME_SLIDER_WIDTH: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DisplayMode {
+    #[default]
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+// Colors runs are drawn in: the level's own, or one that overrides every
+// level's for legibility.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ColorScheme {
+    #[default]
+    Level,
+    HighContrast,
+    Monochrome,
+}
+
+// Display and accessibility options, saved next to the high scores. Loaded
+// in `main` before the window is created so it opens with them; volumes
+// live in `AudioSettings` and bindings in `Bindings`.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    version: u32,
+    pub resolution: (u32, u32),
+    pub display_mode: DisplayMode,
+    pub vsync: bool,
+    pub color_scheme: ColorScheme,
+    pub screen_shake: bool,
+    pub zoom_pulse: bool,
+    pub camera_tilt: bool,
+    // turns off every camera effect and flashing, for motion-sensitive
+    // players
+    pub reduced_motion: bool,
+    // where the settings are saved; `None` keeps them in memory only
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+// The options the settings page lists, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SettingItem {
+    Resolution,
+    DisplayMode,
+    Vsync,
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    ColorScheme,
+    ScreenShake,
+    ZoomPulse,
+    CameraTilt,
+    ReducedMotion,
+}
+
+pub const SETTING_ITEMS: [SettingItem; 11] = [
+    SettingItem::Resolution,
+    SettingItem::DisplayMode,
+    SettingItem::Vsync,
+    SettingItem::MasterVolume,
+    SettingItem::MusicVolume,
+    SettingItem::SfxVolume,
+    SettingItem::ColorScheme,
+    SettingItem::ScreenShake,
+    SettingItem::ZoomPulse,
+    SettingItem::CameraTilt,
+    SettingItem::ReducedMotion,
+];
+
+// Problems `Settings::load` found, logged once the app is running.
+#[derive(Resource)]
+struct SettingsWarnings(Vec<String>);
+
+// Keeps the window in line with `Settings` as they change.
+#[derive(Default)]
+pub struct SettingsPlugin {
+    pub settings: Settings,
+    // as returned by `Settings::load`
+    pub warnings: Vec<String>,
+}
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.settings.clone())
+            .insert_resource(SettingsWarnings(self.warnings.clone()))
+            .add_systems(Startup, settings_warn)
+            .add_systems(
+                Update,
+                settings_apply_window.run_if(resource_changed::<Settings>),
+            );
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            version: SETTINGS_VERSION,
+            resolution: RESOLUTIONS[0],
+            display_mode: DisplayMode::default(),
+            vsync: true,
+            color_scheme: ColorScheme::default(),
+            screen_shake: true,
+            zoom_pulse: true,
+            camera_tilt: true,
+            reduced_motion: false,
+            path: None,
+        }
+    }
+}
+
+impl Settings {
+    // A missing, unreadable or outdated file falls back to the defaults,
+    // like the high scores do, and so does any value out of range. This runs
+    // before logging is set up, so what fell back is returned as warnings for
+    // `SettingsPlugin` to log.
+    pub fn load(path: PathBuf) -> (Settings, Vec<String>) {
+        let mut warnings = Vec::new();
+        let loaded = match fs::read_to_string(&path) {
+            Ok(contents) => match ron::from_str::<Settings>(&contents) {
+                Ok(settings) if settings.version == SETTINGS_VERSION => Some(settings),
+                Ok(settings) => {
+                    warnings.push(format!(
+                        "ignoring settings at {} with unknown version {}",
+                        path.display(),
+                        settings.version
+                    ));
+                    None
+                }
+                Err(err) => {
+                    warnings.push(format!(
+                        "ignoring corrupt settings at {}: {}",
+                        path.display(),
+                        err
+                    ));
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+        let settings = Settings {
+            path: Some(path),
+            ..loaded.unwrap_or_default().validated(&mut warnings)
+        };
+        (settings, warnings)
+    }
+
+    fn validated(mut self, warnings: &mut Vec<String>) -> Settings {
+        let (width, height) = self.resolution;
+        if !(MIN_RESOLUTION.0..=MAX_RESOLUTION.0).contains(&width)
+            || !(MIN_RESOLUTION.1..=MAX_RESOLUTION.1).contains(&height)
+        {
+            let resolution = Settings::default().resolution;
+            warnings.push(format!(
+                "resolution {}x{} is out of range, using {}x{}",
+                width, height, resolution.0, resolution.1
+            ));
+            self.resolution = resolution;
+        }
+        self
+    }
+
+    pub fn save(&self) {
+        let Some(path) = self.path.as_ref() else {
+            return;
+        };
+        if let Err(err) = self.write(path) {
+            warn!("could not save settings to {}: {}", path.display(), err);
+        }
+    }
+
+    fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    // The primary window as these settings describe it.
+    pub fn window(&self) -> Window {
+        let mut window = Window::default();
+        self.apply(&mut window);
+        window
+    }
+
+    fn apply(&self, window: &mut Window) {
+        let (width, height) = self.resolution;
+        window.resolution.set(width as f32, height as f32);
+        window.mode = match self.display_mode {
+            DisplayMode::Windowed => WindowMode::Windowed,
+            DisplayMode::Borderless => WindowMode::BorderlessFullscreen,
+            DisplayMode::Fullscreen => WindowMode::Fullscreen,
+        };
+        window.present_mode = if self.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        };
+    }
+}
+
+impl ColorScheme {
+    // Recolors a level for this scheme. Schemes other than the level's own
+    // hold their colors still, so hue cycling is turned off under them.
+    pub fn apply(&self, level: &mut LevelDefinition) {
+        level.palette = self.palette(&level.palette);
+        for palette in level.theme.palettes.iter_mut() {
+            *palette = self.palette(palette);
+        }
+        if *self != ColorScheme::Level {
+            level.theme.hue_speed = 0.0;
+        }
+    }
+
+    // The palette a level is drawn in under this scheme.
+    pub fn palette(&self, level: &Palette) -> Palette {
+        match self {
+            ColorScheme::Level => level.clone(),
+            ColorScheme::HighContrast => Palette {
+                background: [[0.0, 0.0, 0.0], [0.15, 0.15, 0.15]],
+                walls: [1.0, 1.0, 0.0],
+                center: [0.0, 0.0, 0.0],
+                player: [0.0, 1.0, 1.0],
+                hud: [1.0, 1.0, 1.0],
+            },
+            ColorScheme::Monochrome => {
+                let gray = |[r, g, b]: [f32; 3]| {
+                    let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+                    [luma; 3]
+                };
+                Palette {
+                    background: level.background.map(gray),
+                    walls: gray(level.walls),
+                    center: gray(level.center),
+                    player: gray(level.player),
+                    hud: gray(level.hud),
+                }
+            }
+        }
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "On"
+    } else {
+        "Off"
+    }
+}
+
+fn slider(volume: f32) -> String {
+    let filled = (volume * VOLUME_SLIDER_WIDTH as f32).round() as usize;
+    format!(
+        "[{}{}] {:>3}%",
+        "#".repeat(filled),
+        "-".repeat(VOLUME_SLIDER_WIDTH.saturating_sub(filled)),
+        (volume * 100.0).round()
+    )
+}
+
+// Steps through `options` from `current`, wrapping around.
+fn cycle<T: PartialEq + Copy>(options: &[T], current: T, step: i32) -> T {
+    let index = options
+        .iter()
+        .position(|option| *option == current)
+        .unwrap_or_default();
+    let index = (index as i32 + step).rem_euclid(options.len() as i32);
+    options[index as usize]
+}
+
+impl SettingItem {
+    // Volumes need `SoundPlugin`'s mixer and are left out without it.
+    pub fn available(&self, audio: Option<&AudioSettings>) -> bool {
+        audio.is_some()
+            || !matches!(
+                self,
+                SettingItem::MasterVolume | SettingItem::MusicVolume | SettingItem::SfxVolume
+            )
+    }
+
+    pub fn label(&self, settings: &Settings, audio: Option<&AudioSettings>) -> String {
+        let volume = |channel: fn(&AudioSettings) -> f32| {
+            slider(audio.map_or(0.0, channel))
+        };
+        match self {
+            SettingItem::Resolution => {
+                format!("Resolution: {}x{}", settings.resolution.0, settings.resolution.1)
+            }
+            SettingItem::DisplayMode => format!("Display: {:?}", settings.display_mode),
+            SettingItem::Vsync => format!("VSync: {}", on_off(settings.vsync)),
+            SettingItem::MasterVolume => format!("Master: {}", volume(|audio| audio.master)),
+            SettingItem::MusicVolume => format!("Music: {}", volume(|audio| audio.music)),
+            SettingItem::SfxVolume => format!("Effects: {}", volume(|audio| audio.sfx)),
+            SettingItem::ColorScheme => format!("Colors: {:?}", settings.color_scheme),
+            SettingItem::ScreenShake => format!("Screen Shake: {}", on_off(settings.screen_shake)),
+            SettingItem::ZoomPulse => format!("Zoom Pulse: {}", on_off(settings.zoom_pulse)),
+            SettingItem::CameraTilt => format!("Camera Tilt: {}", on_off(settings.camera_tilt)),
+            SettingItem::ReducedMotion => {
+                format!("Reduced Motion: {}", on_off(settings.reduced_motion))
+            }
+        }
+    }
+
+    // Moves the option one `step` along, -1 or 1, and saves whatever
+    // changed. Toggles flip either way.
+    pub fn adjust(&self, step: i32, settings: &mut Settings, audio: Option<&mut AudioSettings>) {
+        let volume = |audio: Option<&mut AudioSettings>, channel: fn(&mut AudioSettings) -> &mut f32| {
+            if let Some(audio) = audio {
+                let value = channel(audio);
+                *value = ((*value + VOLUME_STEP * step as f32) / VOLUME_STEP).round() * VOLUME_STEP;
+                *value = value.clamp(0.0, 1.0);
+                audio.save();
+            }
+        };
+        match self {
+            SettingItem::MasterVolume => return volume(audio, |audio| &mut audio.master),
+            SettingItem::MusicVolume => return volume(audio, |audio| &mut audio.music),
+            SettingItem::SfxVolume => return volume(audio, |audio| &mut audio.sfx),
+            SettingItem::Resolution => {
+                settings.resolution = cycle(&RESOLUTIONS, settings.resolution, step)
+            }
+            SettingItem::DisplayMode => {
+                settings.display_mode = cycle(
+                    &[
+                        DisplayMode::Windowed,
+                        DisplayMode::Borderless,
+                        DisplayMode::Fullscreen,
+                    ],
+                    settings.display_mode,
+                    step,
+                )
+            }
+            SettingItem::Vsync => settings.vsync = !settings.vsync,
+            SettingItem::ColorScheme => {
+                settings.color_scheme = cycle(
+                    &[
+                        ColorScheme::Level,
+                        ColorScheme::HighContrast,
+                        ColorScheme::Monochrome,
+                    ],
+                    settings.color_scheme,
+                    step,
+                )
+            }
+            SettingItem::ScreenShake => settings.screen_shake = !settings.screen_shake,
+            SettingItem::ZoomPulse => settings.zoom_pulse = !settings.zoom_pulse,
+            SettingItem::CameraTilt => settings.camera_tilt = !settings.camera_tilt,
+            SettingItem::ReducedMotion => settings.reduced_motion = !settings.reduced_motion,
+        }
+        settings.save();
+    }
+}
+
+fn settings_warn(warnings: Res<SettingsWarnings>) {
+    for warning in warnings.0.iter() {
+        warn!("{}", warning);
+    }
+}
+
+fn settings_apply_window(
+    settings: Res<Settings>,
+    mut windows: Query<&mut Window, With<PrimaryWindow>>,
+) {
+    for mut window in windows.iter_mut() {
+        settings.apply(&mut window);
+    }
+}
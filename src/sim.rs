@@ -0,0 +1,283 @@
+use crate::levels::RotationStyle;
+use crate::patterns::WallPattern;
+use bevy::prelude::*;
+use serde::Deserialize;
+
+// Pure gameplay model: everything here works on plain numbers so it can run
+// and be tested without a renderer. Rendering projects these onto sprites.
+// Speeds are per level; the constants below are the default level's.
+
+pub const DEFAULT_SIDES: u32 = 6;
+pub const MIN_SIDES: u32 = 4;       // half a hexagon's sides would close a triangle
+pub const MAX_SIDES: u32 = 8;
+pub const ARENA_MORPH_SPEED: f32 = 1.5;    // sides per second
+pub const PLAYER_MOVE_SPEED: f32 = 300.0;   // degrees per second
+pub const INITIAL_RING_RADIUS: f32 = 100.0;
+pub const WALL_SPIN_SPEED: f32 = 30.0;      // degrees per second
+pub const WALL_SHRINK_SPEED: f32 = 0.3;     // posn per second
+pub const WALL_SPAWN_INTERVAL: f32 = 1.0;
+pub const WALL_RING_RADIUS: f32 = 600.0;
+pub const WALL_HEIGHT: f32 = 10.0;
+pub const CENTER_HEX_RADIUS: f32 = 100.0;
+pub const PLAYER_RING_RADIUS: f32 = CENTER_HEX_RADIUS + 60.0;
+pub const PLAYER_HIT_ARC: f32 = 2.0;        // half-width of the player hitbox, in degrees
+pub const PLAYER_HIT_HEIGHT: f32 = 4.0;     // half-height of the player hitbox, in pixels
+pub const BEAT_PULSE_WIDTH: f32 = 20.0;  // radius added on a beat, in pixels
+
+// The arena polygon. `sides` is fractional while morphing: the last side
+// grows in or shrinks away, its width `sides.fract()` of the others.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Arena {
+    pub sides: f32,
+    pub target_sides: u32,
+}
+
+// Something a pattern, or the rotation's own random schedule, asks the spin
+// to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum RotationEvent {
+    Reverse,
+    Burst,
+}
+
+// The arena's spin. It eases towards the stage's spin speed, flipped by
+// `direction` and sped up while a burst lasts, so reversals and bursts ramp
+// instead of snapping.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct Rotation {
+    pub speed: f32,             // degrees per second, signed
+    pub direction: f32,         // 1.0 or -1.0
+    pub burst: f32,             // seconds of burst left
+    pub next_event: f32,        // seconds until the next random event
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct Wall {
+    pub side: u32,
+    pub distance: f32,          // apothem of the wall's polygon, in pixels
+    pub prev_distance: f32,     // distance at the previous tick, for interpolation
+    pub posn: f32,              // 0.0 to 1.0 where 0.0 is the center hex
+    pub thickness: f32,
+    pub pattern: String,
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena::new(DEFAULT_SIDES)
+    }
+}
+
+impl Arena {
+    pub fn new(sides: u32) -> Arena {
+        let sides = sides.clamp(MIN_SIDES, MAX_SIDES);
+        Arena {
+            sides: sides as f32,
+            target_sides: sides,
+        }
+    }
+
+    pub fn morph_to(&mut self, sides: u32) {
+        self.target_sides = sides.clamp(MIN_SIDES, MAX_SIDES);
+    }
+
+    pub fn is_morphing(&self) -> bool {
+        self.sides != self.target_sides as f32
+    }
+
+    pub fn advance(&mut self, delta_seconds: f32) {
+        let target = self.target_sides as f32;
+        let step = ARENA_MORPH_SPEED * delta_seconds;
+        self.sides = if self.sides < target {
+            (self.sides + step).min(target)
+        } else {
+            (self.sides - step).max(target)
+        };
+    }
+
+    pub fn lerp(&self, other: &Arena, alpha: f32) -> Arena {
+        Arena {
+            sides: self.sides.lerp(other.sides, alpha),
+            target_sides: other.target_sides,
+        }
+    }
+
+    // Sides that take up any room, including one that is morphing.
+    pub fn side_count(&self) -> u32 {
+        self.sides.ceil() as u32
+    }
+
+    // Start angle and angular width of `side` at arena rotation `theta`.
+    pub fn sector(&self, theta: f32, side: u32) -> (f32, f32) {
+        let width = 360.0 / self.sides;
+        let room = (self.sides - side as f32).clamp(0.0, 1.0);
+        ((theta + side as f32 * width).rem_euclid(360.0), width * room)
+    }
+
+    // Corners of the band across `side` between the apothems `inner` and
+    // `outer`: inner start, inner end, outer end, outer start. This is the
+    // shape a wall collides with, and neighbouring sides share corners, so
+    // bands at the same apothems meet exactly once the arena has stopped
+    // morphing.
+    pub fn band(&self, theta: f32, side: u32, inner: f32, outer: f32) -> [Vec2; 4] {
+        let (start, width) = self.sector(theta, side);
+        let stretch = (width / 2.0).to_radians().cos().max(f32::EPSILON);
+        let corner = |angle: f32, apothem: f32| {
+            Vec2::from_angle(angle.to_radians()) * apothem / stretch
+        };
+        [
+            corner(start, inner),
+            corner(start + width, inner),
+            corner(start + width, outer),
+            corner(start, outer),
+        ]
+    }
+}
+
+impl Rotation {
+    pub fn new(speed: f32, next_event: f32) -> Rotation {
+        Rotation {
+            speed,
+            direction: 1.0,
+            burst: 0.0,
+            next_event,
+        }
+    }
+
+    pub fn request(&mut self, event: RotationEvent, style: &RotationStyle) {
+        match event {
+            RotationEvent::Reverse => self.direction = -self.direction,
+            RotationEvent::Burst => self.burst = style.burst_duration,
+        }
+    }
+
+    // The speed the spin eases towards while the stage spins at `base_speed`.
+    pub fn target(&self, base_speed: f32, style: &RotationStyle) -> f32 {
+        let burst = if self.burst > 0.0 { style.burst_speed } else { 1.0 };
+        base_speed * self.direction * burst
+    }
+
+    pub fn advance(&mut self, delta_seconds: f32, base_speed: f32, style: &RotationStyle) {
+        let target = self.target(base_speed, style);
+        let step = style.acceleration * delta_seconds;
+        self.speed = if self.speed < target {
+            (self.speed + step).min(target)
+        } else {
+            (self.speed - step).max(target)
+        };
+        self.burst = (self.burst - delta_seconds).max(0.0);
+    }
+
+    // How far the spin is off its steady speed, from -1.0 while reversing to
+    // 1.0 at the height of a burst; the camera tilts by this much.
+    pub fn tilt(&self, base_speed: f32) -> f32 {
+        let steady = base_speed * self.direction;
+        if steady == 0.0 {
+            return 0.0;
+        }
+        ((self.speed - steady) / steady).clamp(-1.0, 1.0)
+    }
+}
+
+impl Wall {
+    pub fn new(side: u32, distance: f32, thickness: f32, pattern: &str) -> Wall {
+        Wall {
+            side,
+            distance,
+            prev_distance: distance,
+            posn: distance / WALL_RING_RADIUS,
+            thickness,
+            pattern: pattern.to_string(),
+        }
+    }
+
+    // Moves the wall inwards at `speed` posn per second; `wall_ring_radius`
+    // is the pulsing radius that `posn` is measured against.
+    pub fn advance(&mut self, delta_seconds: f32, wall_ring_radius: f32, speed: f32) {
+        self.prev_distance = self.distance;
+        self.posn -= speed * delta_seconds;
+        self.distance = wall_ring_radius * self.posn;
+    }
+
+    pub fn reached_center(&self) -> bool {
+        self.distance < CENTER_HEX_RADIUS
+    }
+
+    // Walls are sides of a regular polygon, so the radial band a wall covers
+    // widens towards its corners.
+    pub fn band_at(&self, arena: &Arena, theta: f32, angle: f32) -> (f32, f32) {
+        let (start, width) = arena.sector(theta, self.side);
+        let mid = start + width / 2.0;
+        let stretch = (angle - mid).to_radians().cos().max(f32::EPSILON);
+        (
+            (self.distance - self.thickness / 2.0) / stretch,
+            (self.distance + self.thickness / 2.0) / stretch,
+        )
+    }
+
+    // A wall hits the player when the player's hitbox angle falls inside the
+    // wall's sector and the player's radius falls inside the band there.
+    // A wall on a side that has morphed away has no room left and never hits.
+    pub fn hits_player(
+        &self,
+        arena: &Arena,
+        theta: f32,
+        player_theta: f32,
+        player_radius: f32,
+    ) -> bool {
+        let (start, width) = arena.sector(theta, self.side);
+        if width <= 0.0 {
+            return false;
+        }
+        [player_theta - PLAYER_HIT_ARC, player_theta, player_theta + PLAYER_HIT_ARC]
+            .iter()
+            .filter(|angle| angle_in_sector(**angle, start, width))
+            .any(|angle| {
+                let (inner, outer) = self.band_at(arena, theta, *angle);
+                player_radius + PLAYER_HIT_HEIGHT >= inner
+                    && player_radius - PLAYER_HIT_HEIGHT <= outer
+            })
+    }
+}
+
+// Where the player ends up moving `step` degrees from `from`. The move is
+// taken in increments no wider than the hitbox and stops at the last free
+// angle, so a fast step cannot jump across a wall's edge.
+pub fn sweep_player(from: f32, step: f32, blocked: impl Fn(f32) -> bool) -> f32 {
+    let increments = (step.abs() / PLAYER_HIT_ARC).ceil().max(1.0);
+    let mut theta = from;
+    for increment in 1..=increments as u32 {
+        let next = (from + step * increment as f32 / increments).rem_euclid(360.0);
+        if blocked(next) {
+            break;
+        }
+        theta = next;
+    }
+    theta
+}
+
+pub fn angle_in_sector(angle: f32, start: f32, width: f32) -> bool {
+    (angle - start).rem_euclid(360.0) <= width
+}
+
+// The walls a pattern spawns in an arena with `side_count` sides, with the
+// first row on the wall ring and later rows stacked outwards by their spacing.
+pub fn pattern_walls(pattern: &WallPattern, side_count: u32) -> Vec<Wall> {
+    let mut walls = Vec::new();
+    let mut offset = 0.0;
+    for (row_index, row) in pattern.rows.iter().enumerate() {
+        if row_index > 0 {
+            offset += row.spacing;
+        }
+        let thickness = row.thickness.unwrap_or(WALL_HEIGHT);
+        for side in row.resolve_sides(side_count) {
+            walls.push(Wall::new(side, WALL_RING_RADIUS + offset, thickness, &pattern.name));
+        }
+    }
+    walls
+}
+
+// Seconds to wait before the next pattern, so it only starts once this one
+// has fully entered the ring.
+pub fn pattern_spawn_delay(pattern: &WallPattern, spawn_interval: f32, wall_speed: f32) -> f32 {
+    spawn_interval + pattern.depth() / WALL_RING_RADIUS / wall_speed
+}
@@ -0,0 +1,257 @@
+use crate::game::{ActiveRun, MilestoneReached, PlayerHit};
+use crate::highscore::config_dir;
+use crate::{Game, GameState};
+use bevy::asset::LoadState;
+use bevy::audio::Volume;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const AUDIO_SETTINGS_VERSION: u32 = 1;
+const AUDIO_SETTINGS_FILE: &str = "audio.ron";
+
+// Sound effects and announcer lines. Anything can send one; `SoundPlugin`
+// plays it at the mixer's SFX volume.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Sfx {
+    MenuMove,
+    MenuSelect,
+    Death,
+    StageUp,
+    Begin,
+    GameOver,
+    Excellent,
+}
+
+const SFX: [Sfx; 7] = [
+    Sfx::MenuMove,
+    Sfx::MenuSelect,
+    Sfx::Death,
+    Sfx::StageUp,
+    Sfx::Begin,
+    Sfx::GameOver,
+    Sfx::Excellent,
+];
+
+// Mixer levels from 0.0 to 1.0, saved next to the high scores. Music and
+// SFX are scaled by `master`, and `muted` silences everything.
+#[derive(Resource, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AudioSettings {
+    version: u32,
+    pub master: f32,
+    pub music: f32,
+    pub sfx: f32,
+    pub muted: bool,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+// Sounds that fail to load are dropped, and play as silence.
+#[derive(Resource)]
+struct SoundLibrary(HashMap<Sfx, Handle<AudioSource>>);
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Sfx>()
+            .insert_resource(AudioSettings::load(config_dir().join(AUDIO_SETTINGS_FILE)))
+            .add_systems(Startup, load_sounds)
+            .add_systems(
+                Update,
+                (
+                    sound_toggle_mute,
+                    sound_begin.run_if(resource_added::<ActiveRun>),
+                    sound_game_events,
+                    sound_missing,
+                    sound_play,
+                )
+                    .chain(),
+            )
+            .add_systems(OnEnter(GameState::GameOver), sound_game_over);
+    }
+}
+
+impl Sfx {
+    fn path(&self) -> &'static str {
+        match self {
+            Sfx::MenuMove => "sounds/menu_move.ogg",
+            Sfx::MenuSelect => "sounds/menu_select.ogg",
+            Sfx::Death => "sounds/death.ogg",
+            Sfx::StageUp => "sounds/stage_up.ogg",
+            Sfx::Begin => "sounds/announcer/begin.ogg",
+            Sfx::GameOver => "sounds/announcer/game_over.ogg",
+            Sfx::Excellent => "sounds/announcer/excellent.ogg",
+        }
+    }
+}
+
+impl AudioSettings {
+    fn defaults(path: PathBuf) -> AudioSettings {
+        AudioSettings {
+            version: AUDIO_SETTINGS_VERSION,
+            master: 1.0,
+            music: 0.8,
+            sfx: 1.0,
+            muted: false,
+            path,
+        }
+    }
+
+    // A missing, unreadable or outdated file falls back to the defaults,
+    // like the high scores do.
+    pub fn load(path: PathBuf) -> AudioSettings {
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(_) => return AudioSettings::defaults(path),
+        };
+
+        match ron::from_str::<AudioSettings>(&contents) {
+            Ok(settings) if settings.version == AUDIO_SETTINGS_VERSION => {
+                AudioSettings { path, ..settings }.validated()
+            }
+            Ok(settings) => {
+                warn!(
+                    "ignoring audio settings at {} with unknown version {}",
+                    path.display(),
+                    settings.version
+                );
+                AudioSettings::defaults(path)
+            }
+            Err(err) => {
+                warn!(
+                    "ignoring corrupt audio settings at {}: {}",
+                    path.display(),
+                    err
+                );
+                AudioSettings::defaults(path)
+            }
+        }
+    }
+
+    // Volumes outside 0.0 to 1.0 fall back to their defaults.
+    fn validated(mut self) -> AudioSettings {
+        let defaults = AudioSettings::defaults(PathBuf::new());
+        for (name, volume, default) in [
+            ("master", &mut self.master, defaults.master),
+            ("music", &mut self.music, defaults.music),
+            ("sfx", &mut self.sfx, defaults.sfx),
+        ] {
+            if !(0.0..=1.0).contains(volume) {
+                warn!("{} volume {} is out of range, using {}", name, volume, default);
+                *volume = default;
+            }
+        }
+        self
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = self.write(&self.path) {
+            warn!(
+                "could not save audio settings to {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+
+    fn write(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    fn volume(&self, channel: f32) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            (self.master * channel).clamp(0.0, 1.0)
+        }
+    }
+
+    pub fn music_volume(&self) -> f32 {
+        self.volume(self.music)
+    }
+
+    pub fn sfx_volume(&self) -> f32 {
+        self.volume(self.sfx)
+    }
+}
+
+fn load_sounds(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(SoundLibrary(
+        SFX.iter()
+            .map(|sfx| (*sfx, asset_server.load(sfx.path())))
+            .collect(),
+    ));
+}
+
+fn sound_missing(asset_server: Res<AssetServer>, mut library: ResMut<SoundLibrary>) {
+    library.0.retain(|sfx, source| {
+        let failed = asset_server.load_state(&*source) == LoadState::Failed;
+        if failed {
+            warn!("could not load {}, {:?} stays silent", sfx.path(), sfx);
+        }
+        !failed
+    });
+}
+
+fn sound_toggle_mute(input: Res<ButtonInput<KeyCode>>, mut settings: ResMut<AudioSettings>) {
+    if input.just_pressed(KeyCode::KeyM) {
+        settings.muted = !settings.muted;
+        info!("audio {}", if settings.muted { "muted" } else { "unmuted" });
+        settings.save();
+    }
+}
+
+fn sound_begin(mut sounds: EventWriter<Sfx>) {
+    sounds.send(Sfx::Begin);
+}
+
+fn sound_game_events(
+    mut hits: EventReader<PlayerHit>,
+    mut milestones: EventReader<MilestoneReached>,
+    mut sounds: EventWriter<Sfx>,
+) {
+    if hits.read().count() > 0 {
+        sounds.send(Sfx::Death);
+    }
+    if milestones.read().count() > 0 {
+        sounds.send(Sfx::StageUp);
+    }
+}
+
+// A run that ends without a wall hitting the player was survived.
+fn sound_game_over(game: Res<Game>, mut sounds: EventWriter<Sfx>) {
+    sounds.send(if game.killed_by.is_some() {
+        Sfx::GameOver
+    } else {
+        Sfx::Excellent
+    });
+}
+
+fn sound_play(
+    mut commands: Commands,
+    mut sounds: EventReader<Sfx>,
+    library: Res<SoundLibrary>,
+    settings: Res<AudioSettings>,
+) {
+    let volume = settings.sfx_volume();
+    if volume <= 0.0 {
+        sounds.clear();
+        return;
+    }
+    for sfx in sounds.read() {
+        if let Some(source) = library.0.get(sfx) {
+            commands.spawn(AudioBundle {
+                source: source.clone(),
+                settings: PlaybackSettings::DESPAWN.with_volume(Volume::new(volume)),
+            });
+        }
+    }
+}
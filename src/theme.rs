@@ -0,0 +1,110 @@
+use crate::levels::{Palette, ThemeStyle};
+use bevy::prelude::*;
+
+// The colors a run is drawn in, resolved once a frame from the level's
+// palettes: swaps blend from one palette into the next, the hue cycles,
+// and new stages flash or invert everything.
+#[derive(Resource, Debug, Clone)]
+pub struct Theme {
+    time: f32,
+    flash: f32,                 // 1.0 right after a new stage, fading to 0.0
+    inverted: bool,
+    colors: ThemeColors,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThemeColors {
+    pub background: [Color; 2],
+    pub walls: Color,
+    pub center: Color,
+    pub player: Color,
+    pub hud: Color,
+}
+
+impl ThemeColors {
+    pub fn background(&self, index: u32) -> Color {
+        self.background[index as usize % self.background.len()]
+    }
+}
+
+impl Theme {
+    pub fn new(base: &Palette, style: &ThemeStyle) -> Theme {
+        let mut theme = Theme {
+            time: 0.0,
+            flash: 0.0,
+            inverted: false,
+            colors: ThemeColors {
+                background: [base.background(0), base.background(1)],
+                walls: base.walls(),
+                center: base.center(),
+                player: base.player(),
+                hud: base.hud(),
+            },
+        };
+        theme.advance(0.0, base, style);
+        theme
+    }
+
+    pub fn colors(&self) -> &ThemeColors {
+        &self.colors
+    }
+
+    pub fn advance(&mut self, delta_seconds: f32, base: &Palette, style: &ThemeStyle) {
+        self.time += delta_seconds;
+        self.flash = (self.flash - delta_seconds / style.flash_duration.max(f32::EPSILON)).max(0.0);
+
+        let palette = self.palette(base, style);
+        let finish = |[r, g, b]: [f32; 3]| self.finish(Color::rgb(r, g, b), style);
+        self.colors = ThemeColors {
+            background: palette.background.map(finish),
+            walls: finish(palette.walls),
+            center: finish(palette.center),
+            player: finish(palette.player),
+            hud: finish(palette.hud),
+        };
+    }
+
+    pub fn new_stage(&mut self, style: &ThemeStyle) {
+        if style.flash_duration > 0.0 {
+            self.flash = 1.0;
+        }
+        if style.invert_on_stage {
+            self.inverted = !self.inverted;
+        }
+    }
+
+    // The palette in effect: the current one of the swap cycle, blending in
+    // from the previous one for `swap_duration` after each swap.
+    fn palette(&self, base: &Palette, style: &ThemeStyle) -> Palette {
+        if style.palettes.is_empty() || style.swap_interval <= 0.0 {
+            return base.clone();
+        }
+        let cycle: Vec<&Palette> = std::iter::once(base).chain(style.palettes.iter()).collect();
+        let swaps = (self.time / style.swap_interval).floor() as usize;
+        let current = cycle[swaps % cycle.len()];
+        let since = self.time - swaps as f32 * style.swap_interval;
+        if swaps == 0 || since >= style.swap_duration {
+            return current.clone();
+        }
+        let previous = cycle[(swaps - 1) % cycle.len()];
+        previous.lerp(current, since / style.swap_duration)
+    }
+
+    fn finish(&self, color: Color, style: &ThemeStyle) -> Color {
+        let [hue, saturation, lightness, _] = color.as_hsla_f32();
+        let shifted = Color::hsl(
+            (hue + style.hue_speed * self.time).rem_euclid(360.0),
+            saturation,
+            lightness,
+        );
+        let [mut r, mut g, mut b, _] = shifted.as_rgba_f32();
+        if self.inverted {
+            [r, g, b] = [1.0 - r, 1.0 - g, 1.0 - b];
+        }
+        Color::rgb(
+            r + (1.0 - r) * self.flash,
+            g + (1.0 - g) * self.flash,
+            b + (1.0 - b) * self.flash,
+        )
+    }
+}
@@ -0,0 +1,1152 @@
+use bevy::prelude::*;
+use bevy::input::keyboard::{Key, KeyboardInput, NativeKey};
+use bevy::input::ButtonState;
+use bevy::time::TimeUpdateStrategy;
+use bevy::window::PrimaryWindow;
+use erbevy::camera::{CameraEffects, CameraRig};
+use erbevy::controls::{Action, Bindings, BothPressed, ControlsScreenPlugin, ACTIONS};
+use erbevy::game::{ActiveRun, PlayerHit, RestartRun};
+use erbevy::highscore::HighScores;
+use erbevy::levels::{
+    CurrentLevel, LevelDefinition, LevelLibrary, Milestone, Palette, RotationStyle,
+    SelectedLevel, ThemeStyle,
+};
+use erbevy::menu::MenuPlugin;
+use erbevy::music::{BeatClock, BeatMap};
+use erbevy::patterns::{PatternLibrary, PatternRow, PatternSet, WallPattern};
+use erbevy::replay::{Replay, ReplayPlayback, ReplayPlugin, ReplayRecording};
+use erbevy::seed::SeedMode;
+use erbevy::settings::{ColorScheme, SettingItem, Settings, SettingsPlugin};
+use erbevy::sound::AudioSettings;
+use erbevy::theme::Theme;
+use erbevy::sim::{
+    sweep_player, Arena, Rotation, RotationEvent, Wall, CENTER_HEX_RADIUS, MAX_SIDES, MIN_SIDES,
+    PLAYER_HIT_ARC, WALL_RING_RADIUS,
+};
+use erbevy::{headless_app, Game, GameState};
+use std::time::Duration;
+
+const TICK_RATE: u32 = 60;
+const SEED: u64 = 42;
+
+fn pattern(name: &str, sides: &[u32]) -> WallPattern {
+    WallPattern {
+        name: name.to_string(),
+        rows: vec![PatternRow {
+            sides: sides.to_vec(),
+            ..default()
+        }],
+        ..default()
+    }
+}
+
+// A headless app that advances exactly one fixed tick per `update`.
+fn start_run(patterns: Vec<WallPattern>) -> App {
+    start(headless_app(SeedMode::Fixed(SEED)), patterns)
+}
+
+fn start(mut app: App, patterns: Vec<WallPattern>) -> App {
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(
+        Duration::from_secs_f64(1.0 / TICK_RATE as f64),
+    ))
+    .insert_resource(PatternLibrary::new(patterns));
+    app.update();
+
+    app.world
+        .resource_mut::<NextState<GameState>>()
+        .set(GameState::Playing);
+    app.update();
+    app
+}
+
+fn step(app: &mut App, ticks: u32) {
+    for _ in 0..ticks {
+        app.update();
+    }
+}
+
+fn seconds(seconds: f32) -> u32 {
+    (seconds * TICK_RATE as f32) as u32
+}
+
+fn walls(app: &mut App) -> Vec<Wall> {
+    app.world
+        .query::<&Wall>()
+        .iter(&app.world)
+        .cloned()
+        .collect()
+}
+
+fn state(app: &App) -> GameState {
+    app.world.resource::<State<GameState>>().get().clone()
+}
+
+#[test]
+fn walls_spawn_every_spawn_period() {
+    let mut app = start_run(vec![pattern("single", &[3])]);
+
+    step(&mut app, seconds(0.9));
+    assert!(walls(&mut app).is_empty());
+
+    step(&mut app, seconds(0.2));
+    assert_eq!(walls(&mut app).len(), 1);
+
+    step(&mut app, seconds(1.0));
+    assert_eq!(walls(&mut app).len(), 2);
+    assert_eq!(state(&app), GameState::Playing);
+}
+
+#[test]
+fn walls_despawn_inside_center_hex() {
+    let mut app = start_run(vec![pattern("empty", &[])]);
+    app.world
+        .spawn(Wall::new(3, CENTER_HEX_RADIUS + 1.0, 10.0, "near"));
+    app.world.spawn(Wall::new(3, WALL_RING_RADIUS, 10.0, "far"));
+
+    step(&mut app, 2);
+
+    let remaining = walls(&mut app);
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].pattern, "far");
+}
+
+#[test]
+fn stationary_player_collides_with_wall() {
+    let mut app = start_run(vec![pattern("target", &[0, 0, 0])]);
+    // Side 0 sweeps over this angle while the first wall closes in.
+    app.world.resource_mut::<Game>().player.theta = 130.0;
+
+    for _ in 0..seconds(10.0) {
+        app.update();
+        if state(&app) == GameState::GameOver {
+            break;
+        }
+    }
+
+    assert_eq!(state(&app), GameState::GameOver);
+    assert_eq!(
+        app.world.resource::<Game>().killed_by.as_deref(),
+        Some("target")
+    );
+    let events = app.world.resource::<Events<PlayerHit>>();
+    let hits: Vec<String> = events
+        .get_reader()
+        .read(events)
+        .map(|hit| hit.pattern.clone())
+        .collect();
+    assert_eq!(hits, vec!["target"]);
+}
+
+#[test]
+fn slow_frame_stops_the_run_on_the_hit_tick() {
+    let lose = || {
+        let mut app = start_run(vec![pattern("target", &[0, 0, 0])]);
+        app.world.resource_mut::<Game>().player.theta = 130.0;
+        app
+    };
+
+    let mut single = lose();
+    let mut frames = 0;
+    while state(&single) != GameState::GameOver {
+        assert!(frames < seconds(10.0));
+        single.update();
+        frames += 1;
+    }
+
+    // One slow frame runs the last few ticks of the run and several past it.
+    let mut batched = lose();
+    step(&mut batched, frames - 5);
+    batched.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+        250,
+    )));
+    batched.update();
+
+    assert_eq!(state(&batched), GameState::Playing);
+    batched.update();
+    assert_eq!(state(&batched), GameState::GameOver);
+    assert_eq!(
+        batched.world.resource::<Game>().score,
+        single.world.resource::<Game>().score
+    );
+    assert_eq!(batched.world.resource::<Events<PlayerHit>>().len(), 1);
+}
+
+#[test]
+fn spawner_records_the_pattern_index() {
+    let mut app = headless_app(SeedMode::Fixed(SEED));
+    app.insert_resource(LevelLibrary::new(vec![LevelDefinition {
+        patterns: vec!["target".to_string()],
+        ..default()
+    }]));
+    let mut app = start(app, vec![pattern("other", &[]), pattern("target", &[0])]);
+    assert_eq!(app.world.resource::<Game>().pattern, None);
+
+    // The index is into the whole library, not the level's own patterns.
+    step(&mut app, seconds(1.1));
+    assert_eq!(app.world.resource::<Game>().pattern, Some(1));
+}
+
+#[test]
+fn lost_run_stays_in_view_until_game_over_ends() {
+    let mut app = start_run(vec![pattern("target", &[0, 0, 0])]);
+    app.world.resource_mut::<Game>().player.theta = 130.0;
+
+    for _ in 0..seconds(10.0) {
+        app.update();
+        if state(&app) == GameState::GameOver {
+            break;
+        }
+    }
+    step(&mut app, 2);
+
+    // The walls of the lost run are kept for the game over screen.
+    assert_eq!(state(&app), GameState::GameOver);
+    assert!(app.world.contains_resource::<ActiveRun>());
+    assert!(!walls(&mut app).is_empty());
+
+    // Retrying starts over from an empty arena.
+    app.world
+        .resource_mut::<NextState<GameState>>()
+        .set(GameState::Playing);
+    app.update();
+    assert_eq!(state(&app), GameState::Playing);
+    assert!(walls(&mut app).is_empty());
+}
+
+#[test]
+fn moving_player_avoids_wall() {
+    let mut app = start_run(vec![pattern("target", &[0, 0, 0])]);
+    app.world.resource_mut::<Game>().player.theta = 130.0;
+    app.world
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .press(KeyCode::ArrowRight);
+
+    step(&mut app, seconds(0.5));
+    app.world
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .release(KeyCode::ArrowRight);
+    step(&mut app, seconds(5.0));
+
+    assert_eq!(state(&app), GameState::Playing);
+}
+
+#[test]
+fn rotation_follows_bindings() {
+    let mut app = start_run(vec![pattern("empty", &[])]);
+    let player_theta = |app: &App| app.world.resource::<Game>().player.theta;
+
+    app.world
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .press(KeyCode::KeyD);
+    step(&mut app, seconds(0.1));
+    assert!(player_theta(&app) > 20.0);
+    app.world
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .release(KeyCode::KeyD);
+    step(&mut app, 1);
+
+    // Rebinding drops the default keys of the action.
+    app.world
+        .resource_mut::<Bindings>()
+        .bind_key(Action::RotateRight, KeyCode::KeyL);
+    let before = player_theta(&app);
+    app.world
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .press(KeyCode::ArrowRight);
+    step(&mut app, seconds(0.1));
+    assert_eq!(player_theta(&app), before);
+
+    app.world
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .press(KeyCode::KeyL);
+    step(&mut app, seconds(0.1));
+    assert!(player_theta(&app) > before + 20.0);
+}
+
+#[test]
+fn default_keys_belong_to_one_action_each() {
+    let bindings = Bindings::default();
+    let mut keys: Vec<KeyCode> = ACTIONS
+        .iter()
+        .flat_map(|action| bindings.keys(*action).iter().copied())
+        .collect();
+    let count = keys.len();
+    keys.sort_by_key(|key| format!("{:?}", key));
+    keys.dedup();
+    assert_eq!(keys.len(), count);
+}
+
+#[test]
+fn rebinding_a_bound_key_moves_it() {
+    let mut bindings = Bindings::default();
+
+    // Pause keeps Escape when Back takes P.
+    assert_eq!(bindings.bind_key(Action::Back, KeyCode::KeyP), vec![Action::Pause]);
+    assert_eq!(bindings.keys(Action::Pause), [KeyCode::Escape]);
+
+    // Taking Back's only button swaps it for the one Confirm had.
+    assert_eq!(
+        bindings.bind_button(Action::Confirm, GamepadButtonType::East),
+        vec![Action::Back]
+    );
+    assert_eq!(bindings.buttons(Action::Back), [GamepadButtonType::South]);
+    assert!(bindings.bind_key(Action::Up, KeyCode::KeyI).is_empty());
+}
+
+#[test]
+fn rebinding_cancels_with_back_or_pause() {
+    let mut app = headless_app(SeedMode::Fixed(SEED));
+    app.add_plugins(ControlsScreenPlugin);
+    app.update();
+    app.world
+        .resource_mut::<NextState<GameState>>()
+        .set(GameState::Controls);
+    app.update();
+
+    // Rebinding reads keys as they go down, so they come in as events.
+    let tap = |app: &mut App, key_code: KeyCode| {
+        for state in [ButtonState::Pressed, ButtonState::Released] {
+            app.world.send_event(KeyboardInput {
+                key_code,
+                logical_key: Key::Unidentified(NativeKey::Unidentified),
+                state,
+                window: Entity::PLACEHOLDER,
+            });
+            app.update();
+        }
+    };
+    let defaults = Bindings::default();
+
+    // The first item rebinds RotateLeft.
+    tap(&mut app, KeyCode::Enter);
+    tap(&mut app, KeyCode::Escape);
+    tap(&mut app, KeyCode::Enter);
+    tap(&mut app, KeyCode::Backspace);
+    assert_eq!(state(&app), GameState::Controls);
+    assert_eq!(
+        app.world.resource::<Bindings>().keys(Action::RotateLeft),
+        defaults.keys(Action::RotateLeft)
+    );
+
+    tap(&mut app, KeyCode::Enter);
+    tap(&mut app, KeyCode::KeyJ);
+    assert_eq!(
+        app.world.resource::<Bindings>().keys(Action::RotateLeft),
+        [KeyCode::KeyJ]
+    );
+}
+
+#[test]
+fn both_directions_follow_policy() {
+    let mut app = start_run(vec![pattern("empty", &[])]);
+    // Signed degrees moved over a tenth of a second, holding D then A.
+    let moved = |app: &mut App| {
+        let before = app.world.resource::<Game>().player.theta;
+        step(app, seconds(0.1));
+        let after = app.world.resource::<Game>().player.theta;
+        (after - before + 180.0).rem_euclid(360.0) - 180.0
+    };
+
+    app.world
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .press(KeyCode::KeyD);
+    step(&mut app, 1);
+    app.world
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .press(KeyCode::KeyA);
+    assert!(moved(&mut app) < -20.0);
+
+    app.world.resource_mut::<Bindings>().both_pressed = BothPressed::FirstPressed;
+    assert!(moved(&mut app) > 20.0);
+
+    app.world.resource_mut::<Bindings>().both_pressed = BothPressed::Cancel;
+    assert_eq!(moved(&mut app), 0.0);
+}
+
+#[test]
+fn side_bands_meet_at_corners() {
+    for sides in MIN_SIDES..=MAX_SIDES {
+        let arena = Arena::new(sides);
+        for side in 0..sides {
+            let band = arena.band(17.0, side, 90.0, 110.0);
+            let next = arena.band(17.0, (side + 1) % sides, 90.0, 110.0);
+            assert!(band[1].distance(next[0]) < 1e-3, "{} sides", sides);
+            assert!(band[2].distance(next[3]) < 1e-3, "{} sides", sides);
+        }
+    }
+
+    // A wall's band is the shape it collides with.
+    let arena = Arena::new(6);
+    let wall = Wall::new(2, 300.0, 20.0, "wall");
+    let band = arena.band(17.0, 2, 290.0, 310.0);
+    let corner = band[0].to_angle().to_degrees().rem_euclid(360.0);
+    let (inner, outer) = wall.band_at(&arena, 17.0, corner);
+    assert!((band[0].length() - inner).abs() < 1e-3);
+    assert!((band[3].length() - outer).abs() < 1e-3);
+}
+
+#[test]
+fn fast_movement_stops_at_walls() {
+    let blocked = |theta: f32| (10.0..=12.0).contains(&theta);
+
+    // A single step far past a narrow wall stops just short of it.
+    let theta = sweep_player(0.0, 90.0, blocked);
+    assert!(theta < 10.0 && theta > 10.0 - 2.0 * 2.0, "{}", theta);
+    assert_eq!(sweep_player(20.0, 90.0, blocked), 110.0);
+    assert_eq!(sweep_player(5.0, -10.0, blocked), 355.0);
+}
+
+#[test]
+fn score_timer_ends_run_at_sixty_seconds() {
+    let mut app = start_run(vec![pattern("empty", &[])]);
+
+    step(&mut app, seconds(59.0));
+    assert_eq!(state(&app), GameState::Playing);
+
+    step(&mut app, seconds(1.1));
+    assert_eq!(state(&app), GameState::GameOver);
+    let game = app.world.resource::<Game>();
+    assert!(game.killed_by.is_none());
+    assert!((game.score - 60.0).abs() < 0.1);
+}
+
+#[test]
+fn pattern_sides_follow_side_count() {
+    let gaps = PatternRow {
+        gaps: Some(vec![5]),
+        ..default()
+    };
+    assert_eq!(gaps.resolve_sides(6), vec![0, 1, 2, 3, 4]);
+    assert_eq!(gaps.resolve_sides(4), vec![0, 2, 3]);
+
+    let alternate = PatternRow {
+        sides: vec![1],
+        every: Some(2),
+        ..default()
+    };
+    assert_eq!(alternate.resolve_sides(6), vec![1, 3, 5]);
+    assert_eq!(alternate.resolve_sides(5), vec![1, 3]);
+
+    let listed = PatternRow {
+        sides: vec![0, 5, 6],
+        ..default()
+    };
+    assert_eq!(listed.resolve_sides(4), vec![0, 1, 2]);
+}
+
+fn assert_patterns_leave_a_gap(patterns: &[WallPattern]) {
+    for pattern in patterns.iter() {
+        for side_count in MIN_SIDES..=MAX_SIDES {
+            for row in pattern.rows.iter() {
+                assert!(
+                    row.resolve_sides(side_count).len() < side_count as usize,
+                    "{} closes every side of a {}-sided arena",
+                    pattern.name,
+                    side_count
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn pattern_files_leave_a_gap_for_every_side_count() {
+    for entry in std::fs::read_dir("assets/patterns").unwrap() {
+        let path = entry.unwrap().path();
+        let set: PatternSet = ron::from_str(&std::fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+        assert_patterns_leave_a_gap(&set.patterns);
+    }
+    assert_patterns_leave_a_gap(PatternLibrary::default().patterns());
+}
+
+#[test]
+fn walls_spawn_for_arena_side_count() {
+    let mut app = headless_app(SeedMode::Fixed(SEED));
+    app.insert_resource(LevelLibrary::new(vec![LevelDefinition {
+        sides: 4,
+        ..default()
+    }]));
+    let gaps = WallPattern {
+        name: "gaps".to_string(),
+        rows: vec![PatternRow {
+            gaps: Some(vec![0]),
+            ..default()
+        }],
+        ..default()
+    };
+    let mut app = start(app, vec![gaps]);
+
+    step(&mut app, seconds(1.1));
+
+    let mut sides: Vec<u32> = walls(&mut app).iter().map(|wall| wall.side).collect();
+    sides.sort();
+    assert_eq!(sides, vec![1, 2, 3]);
+}
+
+#[test]
+fn arena_morphs_between_side_counts() {
+    let mut app = headless_app(SeedMode::Fixed(SEED));
+    app.insert_resource(LevelLibrary::new(vec![LevelDefinition {
+        sides: 4,
+        morph_sides: vec![6],
+        morph_interval: 1.0,
+        ..default()
+    }]));
+    let mut app = start(app, vec![pattern("empty", &[])]);
+
+    step(&mut app, seconds(0.9));
+    assert_eq!(app.world.resource::<Game>().arena.sides, 4.0);
+
+    step(&mut app, seconds(0.5));
+    let arena = app.world.resource::<Game>().arena;
+    assert!(arena.is_morphing());
+    assert!(arena.sides > 4.0 && arena.sides < 6.0);
+    assert_eq!(arena.side_count(), 5);
+
+    step(&mut app, seconds(2.0));
+    let arena = app.world.resource::<Game>().arena;
+    assert!(!arena.is_morphing());
+    assert_eq!(arena.sides, 6.0);
+}
+
+#[test]
+fn walls_spawned_while_shrinking_leave_a_gap() {
+    let mut app = headless_app(SeedMode::Fixed(SEED));
+    app.insert_resource(LevelLibrary::new(vec![LevelDefinition {
+        sides: 7,
+        morph_sides: vec![5],
+        morph_interval: 0.5,
+        ..default()
+    }]));
+    let gap = WallPattern {
+        name: "gap".to_string(),
+        rows: vec![PatternRow {
+            gaps: Some(vec![6]),
+            ..default()
+        }],
+        ..default()
+    };
+    let mut app = start(app, vec![gap]);
+
+    // The pattern spawns while the seventh side is shrinking away.
+    step(&mut app, seconds(1.1));
+    let arena = app.world.resource::<Game>().arena;
+    assert!(arena.sides > 6.0 && arena.sides < 7.0);
+    assert!(!walls(&mut app).is_empty());
+
+    step(&mut app, seconds(0.8));
+    let arena = app.world.resource::<Game>().arena;
+    assert!(!arena.is_morphing());
+    let covered: Vec<u32> = walls(&mut app).iter().map(|wall| wall.side).collect();
+    let widest = (0..arena.side_count())
+        .filter(|side| !covered.contains(side))
+        .map(|side| arena.sector(0.0, side).1)
+        .fold(0.0, f32::max);
+    assert!(widest > 2.0 * PLAYER_HIT_ARC);
+}
+
+fn level(name: &str) -> LevelDefinition {
+    LevelDefinition {
+        name: name.to_string(),
+        ..default()
+    }
+}
+
+#[test]
+fn empty_level_library_keeps_the_default_level() {
+    let library = LevelLibrary::new(Vec::new());
+    let default = LevelDefinition::default().name;
+    assert_eq!(library.levels().len(), 1);
+    assert_eq!(library.get("missing").name, default);
+    assert_eq!(library.cycle("missing", -1).name, default);
+}
+
+#[test]
+fn selected_level_sets_the_run_speeds() {
+    let mut app = headless_app(SeedMode::Fixed(SEED));
+    app.insert_resource(LevelLibrary::new(vec![
+        level("slow"),
+        LevelDefinition {
+            spin_speed: 90.0,
+            ..level("fast")
+        },
+    ]))
+    .insert_resource(SelectedLevel("fast".to_string()));
+    let mut app = start(app, vec![pattern("empty", &[])]);
+
+    step(&mut app, seconds(1.0));
+
+    assert_eq!(app.world.resource::<CurrentLevel>().0.name, "fast");
+    assert!((app.world.resource::<Game>().theta - 90.0).abs() < 2.0);
+}
+
+#[test]
+fn level_without_duration_plays_past_sixty_seconds() {
+    let mut app = headless_app(SeedMode::Fixed(SEED));
+    app.insert_resource(LevelLibrary::new(vec![LevelDefinition {
+        duration: None,
+        milestones: vec![
+            Milestone {
+                time: 30.0,
+                name: "half".to_string(),
+                ..default()
+            },
+            Milestone {
+                time: 60.0,
+                name: "full".to_string(),
+                ..default()
+            },
+        ],
+        ..default()
+    }]));
+    let mut app = start(app, vec![pattern("empty", &[])]);
+
+    step(&mut app, seconds(45.0));
+    assert_eq!(app.world.resource::<Game>().milestone.as_deref(), Some("half"));
+
+    step(&mut app, seconds(20.0));
+    assert_eq!(state(&app), GameState::Playing);
+    assert_eq!(app.world.resource::<Game>().milestone.as_deref(), Some("full"));
+}
+
+#[test]
+fn milestones_ramp_up_the_run_speeds() {
+    let mut app = headless_app(SeedMode::Fixed(SEED));
+    app.insert_resource(LevelLibrary::new(vec![LevelDefinition {
+        milestones: vec![Milestone {
+            time: 1.0,
+            name: "faster".to_string(),
+            wall_speed: 2.0,
+            spawn_rate: 2.0,
+            spin_speed: -1.0,
+        }],
+        ..default()
+    }]));
+    let mut app = start(app, vec![pattern("single", &[3])]);
+
+    step(&mut app, seconds(1.5));
+    assert_eq!(app.world.resource::<Game>().milestone.as_deref(), Some("faster"));
+    let posn = walls(&mut app)[0].posn;
+    assert!((posn - 0.7).abs() < 0.02, "posn {}", posn);
+
+    // The spin eases through zero over half a second, then unwinds at full
+    // speed.
+    step(&mut app, seconds(0.5));
+    assert!((app.world.resource::<Game>().theta - 15.0).abs() < 2.0);
+
+    // The spawner picks up the faster rate after its next spawn: walls at
+    // 2.0 and 2.5 seconds, the first one having reached the center.
+    step(&mut app, seconds(0.6));
+    assert_eq!(walls(&mut app).len(), 2);
+}
+
+#[test]
+fn stage_progress_runs_between_milestones() {
+    let mut level = LevelDefinition {
+        milestones: vec![
+            Milestone {
+                time: 10.0,
+                ..default()
+            },
+            Milestone {
+                time: 30.0,
+                ..default()
+            },
+        ],
+        duration: Some(50.0),
+        ..default()
+    };
+    assert_eq!(level.stage_progress(5.0), Some(0.5));
+    assert_eq!(level.stage_progress(20.0), Some(0.5));
+    // After the last milestone the bar runs to the end of the level.
+    assert_eq!(level.stage_progress(40.0), Some(0.5));
+    assert_eq!(level.stage_progress(50.0), None);
+
+    level.duration = None;
+    assert_eq!(level.stage_progress(40.0), None);
+}
+
+#[test]
+fn patterns_request_rotation_events() {
+    let reverse = WallPattern {
+        rotation: Some(RotationEvent::Reverse),
+        ..pattern("reverse", &[])
+    };
+    let mut app = start_run(vec![reverse]);
+
+    // Reversed by the first spawn at one second, the spin eases through zero
+    // and holds theta for half a second before unwinding.
+    step(&mut app, seconds(1.5));
+    let theta = app.world.resource::<Game>().theta;
+    assert!((theta - 30.0).abs() < 3.0, "theta {}", theta);
+    assert_eq!(app.world.resource::<Rotation>().speed, -30.0);
+
+    step(&mut app, seconds(0.4));
+    let theta = app.world.resource::<Game>().theta;
+    assert!((theta - 18.0).abs() < 3.0, "theta {}", theta);
+}
+
+#[test]
+fn random_rotation_events_follow_the_seed() {
+    let spins = || {
+        let mut app = headless_app(SeedMode::Fixed(SEED));
+        app.insert_resource(LevelLibrary::new(vec![LevelDefinition {
+            rotation: RotationStyle {
+                event_interval: Some((0.5, 1.0)),
+                burst_chance: 0.5,
+                ..default()
+            },
+            ..default()
+        }]));
+        let mut app = start(app, vec![pattern("empty", &[])]);
+        (0..10)
+            .map(|_| {
+                step(&mut app, seconds(0.5));
+                app.world.resource::<Rotation>().speed
+            })
+            .collect::<Vec<f32>>()
+    };
+
+    let first = spins();
+    assert_eq!(first, spins());
+    assert!(first.iter().any(|speed| *speed < 0.0));
+    assert!(first.iter().any(|speed| *speed > 30.0));
+}
+
+#[test]
+fn rings_pulse_on_the_beat() {
+    let mut app = headless_app(SeedMode::Fixed(SEED));
+    app.insert_resource(LevelLibrary::new(vec![LevelDefinition {
+        bpm: 60.0,
+        ..default()
+    }]));
+    let mut app = start(app, vec![pattern("empty", &[])]);
+
+    step(&mut app, seconds(1.0) + 1);
+    let game = app.world.resource::<Game>();
+    assert!(game.center_ring_radius > CENTER_HEX_RADIUS + 15.0);
+    assert_eq!(game.player_radius - game.center_ring_radius, 60.0);
+
+    step(&mut app, seconds(0.5));
+    assert!(app.world.resource::<Game>().center_ring_radius < CENTER_HEX_RADIUS + 1.0);
+}
+
+#[test]
+fn beat_maps_carry_on_at_their_bpm() {
+    let mut clock = BeatClock::new(BeatMap {
+        bpm: 60.0,
+        offset: 0.0,
+        beats: vec![0.25, 0.5],
+    });
+    assert_eq!(clock.beat(), 0);
+    assert_eq!(clock.pulse(), 0.0);
+
+    clock.advance(0.3);
+    assert_eq!(clock.beat(), 1);
+    clock.advance(0.3);
+    assert_eq!(clock.beat(), 2);
+    clock.advance(0.95);
+    assert_eq!(clock.beat(), 3);
+    assert!(clock.pulse() > 0.5);
+    clock.advance(1.0);
+    assert_eq!(clock.beat(), 4);
+}
+
+#[test]
+fn level_files_only_name_existing_patterns() {
+    let patterns: Vec<WallPattern> = std::fs::read_dir("assets/patterns")
+        .unwrap()
+        .flat_map(|entry| {
+            let contents = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            ron::from_str::<PatternSet>(&contents).unwrap().patterns
+        })
+        .collect();
+
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir("assets/levels").unwrap() {
+        let path = entry.unwrap().path();
+        let level: LevelDefinition = ron::from_str(&std::fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+
+        for allowed in level.patterns.iter() {
+            let only = LevelDefinition {
+                patterns: vec![allowed.clone()],
+                ..default()
+            };
+            assert!(
+                patterns.iter().any(|pattern| only.allows(pattern)),
+                "{} allows unknown pattern {}",
+                level.name,
+                allowed
+            );
+        }
+        // A milestone the run ends on would never be played.
+        for milestone in level.milestones.iter() {
+            assert!(
+                level.duration.is_none_or(|end| milestone.time < end),
+                "{} ends before reaching {}",
+                level.name,
+                milestone.name
+            );
+        }
+        if let Some(track) = level.music.as_ref() {
+            let sidecar = std::path::Path::new("assets")
+                .join(track)
+                .with_extension("beats.ron");
+            let contents = std::fs::read_to_string(&sidecar)
+                .unwrap_or_else(|err| panic!("{}: {}", sidecar.display(), err));
+            ron::from_str::<BeatMap>(&contents)
+                .unwrap_or_else(|err| panic!("{}: {}", sidecar.display(), err));
+        }
+        names.push(level.name);
+    }
+
+    names.sort();
+    assert_eq!(names, vec!["Hexagon", "Hexagoner", "Hexagonest"]);
+}
+
+#[test]
+fn same_seed_spawns_same_walls() {
+    let patterns = vec![
+        pattern("a", &[0]),
+        pattern("b", &[1, 2]),
+        pattern("c", &[3, 4, 5]),
+    ];
+    let mut first = start_run(patterns.clone());
+    let mut second = start_run(patterns);
+
+    step(&mut first, seconds(2.5));
+    step(&mut second, seconds(2.5));
+
+    let describe = |walls: Vec<Wall>| {
+        let mut walls: Vec<(u32, String)> = walls
+            .into_iter()
+            .map(|wall| (wall.side, wall.pattern))
+            .collect();
+        walls.sort();
+        walls
+    };
+    assert_eq!(describe(walls(&mut first)), describe(walls(&mut second)));
+}
+
+fn replay_patterns() -> Vec<WallPattern> {
+    vec![
+        pattern("a", &[0, 1, 2, 3, 4]),
+        pattern("b", &[1, 2, 3, 4, 5]),
+        pattern("c", &[0, 2, 3, 4, 5]),
+    ]
+}
+
+fn run_to_end(app: &mut App) {
+    for _ in 0..seconds(61.0) {
+        if state(app) == GameState::GameOver {
+            return;
+        }
+        app.update();
+    }
+}
+
+fn outcome(app: &App) -> (Option<String>, f32, f32) {
+    let game = app.world.resource::<Game>();
+    (game.killed_by.clone(), game.score, game.player.theta)
+}
+
+// Plays a scripted run that weaves around for a while and then gives up.
+fn record_run() -> (Replay, (Option<String>, f32, f32)) {
+    let mut app = headless_app(SeedMode::Fixed(SEED));
+    app.add_plugins(ReplayPlugin::default());
+    let mut app = start(app, replay_patterns());
+
+    for (key, duration) in [
+        (KeyCode::ArrowRight, 0.4),
+        (KeyCode::ArrowLeft, 0.7),
+        (KeyCode::ArrowRight, 0.2),
+    ] {
+        app.world.resource_mut::<ButtonInput<KeyCode>>().press(key);
+        step(&mut app, seconds(duration));
+        app.world.resource_mut::<ButtonInput<KeyCode>>().release(key);
+        step(&mut app, seconds(0.3));
+    }
+    run_to_end(&mut app);
+
+    assert_eq!(state(&app), GameState::GameOver);
+    let replay = app.world.resource::<ReplayRecording>().0.clone().unwrap();
+    (replay, outcome(&app))
+}
+
+fn playback_app(replay: Replay) -> App {
+    let mut app = headless_app(SeedMode::Random);
+    app.add_plugins(ReplayPlugin {
+        playback: Some(replay),
+        save_dir: None,
+    });
+    start(app, replay_patterns())
+}
+
+#[test]
+fn replay_reproduces_recorded_run() {
+    let (replay, recorded) = record_run();
+    assert_eq!(replay.seed, SEED);
+
+    let mut app = playback_app(replay);
+    run_to_end(&mut app);
+
+    assert_eq!(state(&app), GameState::GameOver);
+    assert_eq!(outcome(&app), recorded);
+}
+
+// Frames of several ticks each, both while recording and playing back.
+#[test]
+fn replay_of_slow_frames_ends_on_the_hit_tick() {
+    let slow = || TimeUpdateStrategy::ManualDuration(Duration::from_millis(70));
+    let mut app = headless_app(SeedMode::Fixed(SEED));
+    app.add_plugins(ReplayPlugin::default());
+    let mut app = start(app, replay_patterns());
+    app.insert_resource(slow());
+
+    app.world
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .press(KeyCode::ArrowRight);
+    step(&mut app, 3);
+    app.world
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .release(KeyCode::ArrowRight);
+    run_to_end(&mut app);
+
+    assert_eq!(state(&app), GameState::GameOver);
+    let replay = app.world.resource::<ReplayRecording>().0.clone().unwrap();
+    let recorded = outcome(&app);
+    assert_eq!(replay.ticks(), (recorded.1 * TICK_RATE as f32).round() as u32);
+
+    let mut app = playback_app(replay.clone());
+    app.insert_resource(slow());
+    run_to_end(&mut app);
+
+    assert_eq!(outcome(&app), recorded);
+    assert_eq!(app.world.resource::<ReplayPlayback>().tick, replay.ticks());
+}
+
+#[test]
+fn replay_seeking_back_restarts_the_run() {
+    let (replay, recorded) = record_run();
+
+    let mut app = playback_app(replay);
+    step(&mut app, seconds(1.5));
+    let rewind = app.world.resource_mut::<ReplayPlayback>().seek_to(seconds(0.5));
+    assert!(rewind);
+    app.world.send_event(RestartRun);
+    // The restart happens in `Update`, after this frame's tick.
+    step(&mut app, 2);
+    assert_eq!(app.world.resource::<ReplayPlayback>().tick, 1);
+
+    run_to_end(&mut app);
+    assert_eq!(outcome(&app), recorded);
+}
+
+#[test]
+fn replay_file_round_trips() {
+    let (replay, _) = record_run();
+    let path = std::env::temp_dir().join(format!("erbevy-replay-{}.ron", std::process::id()));
+
+    replay.save(&path).unwrap();
+    let loaded = Replay::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded, replay);
+    assert_eq!(loaded.ticks(), replay.ticks());
+}
+
+#[test]
+fn audio_settings_persist_and_mute() {
+    let path = std::env::temp_dir().join(format!("erbevy-audio-{}.ron", std::process::id()));
+    let mut settings = AudioSettings::load(path.clone());
+    settings.master = 0.5;
+    settings.sfx = 0.6;
+    settings.save();
+
+    let mut loaded = AudioSettings::load(path.clone());
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded, settings);
+    assert!((loaded.sfx_volume() - 0.3).abs() < 1e-6);
+
+    loaded.muted = true;
+    assert_eq!(loaded.sfx_volume(), 0.0);
+    assert_eq!(loaded.music_volume(), 0.0);
+}
+
+#[test]
+fn settings_fall_back_and_persist() {
+    let path = std::env::temp_dir().join(format!("erbevy-settings-{}.ron", std::process::id()));
+    std::fs::write(
+        &path,
+        "(resolution: (10, 10), vsync: false, color_scheme: Monochrome)",
+    )
+    .unwrap();
+
+    // The bad resolution falls back on its own; the rest is kept.
+    let (mut settings, warnings) = Settings::load(path.clone());
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(settings.resolution, Settings::default().resolution);
+    assert!(!settings.vsync);
+    assert_eq!(settings.color_scheme, ColorScheme::Monochrome);
+
+    SettingItem::Resolution.adjust(1, &mut settings, None);
+    SettingItem::ColorScheme.adjust(1, &mut settings, None);
+    let (loaded, warnings) = Settings::load(path.clone());
+    assert!(warnings.is_empty());
+    assert_ne!(loaded.resolution, Settings::default().resolution);
+    assert_eq!(loaded.color_scheme, ColorScheme::Level);
+    assert_eq!(loaded, settings);
+
+    std::fs::write(&path, "(version: 99, vsync: false)").unwrap();
+    let (loaded, warnings) = Settings::load(path.clone());
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(warnings.len(), 1);
+    assert!(loaded.vsync);
+}
+
+#[test]
+fn menu_navigates_submenus() {
+    let mut app = headless_app(SeedMode::Fixed(SEED));
+    let scores = std::env::temp_dir().join(format!("erbevy-menu-{}.ron", std::process::id()));
+    app.add_plugins((MenuPlugin, SettingsPlugin::default()))
+        .insert_resource(HighScores::load(scores))
+        .insert_resource(LevelLibrary::new(vec![level("first"), level("second")]));
+    app.update();
+
+    let tap = |app: &mut App, key: KeyCode| {
+        app.world.resource_mut::<ButtonInput<KeyCode>>().press(key);
+        app.update();
+        app.world.resource_mut::<ButtonInput<KeyCode>>().release(key);
+        app.update();
+    };
+
+    // Level Select, then the second level, which returns to the main page.
+    tap(&mut app, KeyCode::ArrowDown);
+    tap(&mut app, KeyCode::Enter);
+    tap(&mut app, KeyCode::ArrowDown);
+    tap(&mut app, KeyCode::Enter);
+    assert_eq!(app.world.resource::<SelectedLevel>().0, "second");
+
+    // Back on the main page focus is still on Level Select; Credits and
+    // back out again.
+    tap(&mut app, KeyCode::ArrowDown);
+    tap(&mut app, KeyCode::ArrowDown);
+    tap(&mut app, KeyCode::ArrowDown);
+    tap(&mut app, KeyCode::Enter);
+    tap(&mut app, KeyCode::Backspace);
+    assert_eq!(state(&app), GameState::Menu);
+
+    for _ in 0..4 {
+        tap(&mut app, KeyCode::ArrowUp);
+    }
+    tap(&mut app, KeyCode::Enter);
+    assert_eq!(state(&app), GameState::Playing);
+}
+
+#[test]
+fn menu_clicks_choose_only_the_clicked_item() {
+    let mut app = headless_app(SeedMode::Fixed(SEED));
+    let scores = std::env::temp_dir().join(format!("erbevy-click-{}.ron", std::process::id()));
+    app.add_plugins((MenuPlugin, SettingsPlugin::default()))
+        .insert_resource(HighScores::load(scores))
+        .insert_resource(LevelLibrary::new(vec![level("first"), level("second")]));
+    // Holding the mouse on the left half of the window also steers left.
+    let mut window = Window::default();
+    window.set_cursor_position(Some(Vec2::new(10.0, 10.0)));
+    app.world.spawn((window, PrimaryWindow));
+    app.update();
+    let selected = app.world.resource::<SelectedLevel>().0.clone();
+
+    let click = |app: &mut App, label: &str| {
+        let mut items = app.world.query::<(Entity, &Text, &Interaction)>();
+        let item = items
+            .iter(&app.world)
+            .find(|(_, text, _)| text.sections[0].value.starts_with(label))
+            .map(|(entity, _, _)| entity)
+            .unwrap_or_else(|| panic!("no item {}", label));
+        app.world.resource_mut::<ButtonInput<MouseButton>>().press(MouseButton::Left);
+        *app.world.get_mut::<Interaction>(item).unwrap() = Interaction::Pressed;
+        app.update();
+        app.world.resource_mut::<ButtonInput<MouseButton>>().release(MouseButton::Left);
+        if let Some(mut interaction) = app.world.get_mut::<Interaction>(item) {
+            *interaction = Interaction::None;
+        }
+        app.update();
+    };
+
+    click(&mut app, "Settings");
+    assert_eq!(app.world.resource::<SelectedLevel>().0, selected);
+    assert!(app.world.resource::<Settings>().screen_shake);
+    click(&mut app, "Screen Shake");
+    assert!(!app.world.resource::<Settings>().screen_shake);
+}
+
+#[test]
+fn theme_blends_swaps_and_flashes() {
+    let base = Palette::default();
+    let other = Palette {
+        walls: [0.0, 0.0, 0.0],
+        ..default()
+    };
+    let style = ThemeStyle {
+        palettes: vec![other],
+        swap_interval: 10.0,
+        swap_duration: 2.0,
+        flash_duration: 0.5,
+        invert_on_stage: true,
+        ..default()
+    };
+    let walls = |theme: &Theme| theme.colors().walls.as_rgba_f32()[0];
+
+    let mut theme = Theme::new(&base, &style);
+    theme.advance(9.0, &base, &style);
+    assert_eq!(walls(&theme), 1.0);
+    // Halfway through the blend into the second palette, then settled.
+    theme.advance(2.0, &base, &style);
+    assert!((walls(&theme) - 0.5).abs() < 1e-3);
+    theme.advance(2.0, &base, &style);
+    assert_eq!(walls(&theme), 0.0);
+
+    // A new stage inverts and flashes towards white, then the flash fades.
+    theme.new_stage(&style);
+    theme.advance(0.25, &base, &style);
+    assert!((walls(&theme) - 1.0).abs() < 1e-3);
+    let background = theme.colors().background(0).as_rgba_f32();
+    assert!(background[2] > 0.5);
+    theme.advance(0.5, &base, &style);
+    assert_eq!(walls(&theme), 1.0);
+    assert_eq!(theme.colors().background(0).as_rgba_f32()[2], 1.0);
+}
+
+#[test]
+fn camera_effects_follow_events_and_settings() {
+    let mut settings = Settings::default();
+    let all = CameraEffects::from_settings(&settings);
+    settings.reduced_motion = true;
+    let none = CameraEffects::from_settings(&settings);
+    assert_eq!(
+        none,
+        CameraEffects {
+            zoom_pulse: false,
+            shake: false,
+            tilt: false,
+        }
+    );
+
+    let mut rig = CameraRig::default();
+    rig.advance(0.01);
+    rig.hit();
+    rig.new_stage();
+    assert_eq!(rig.transform(none, 1.0, 10.0), Transform::IDENTITY);
+
+    // A death shakes the view and a new stage zooms in, both wearing off.
+    let shaken = rig.transform(all, 0.0, 0.0);
+    assert!(shaken.translation.truncate().length() > 0.0);
+    assert!(shaken.scale.x < 1.0);
+    rig.advance(1.0);
+    let settled = rig.transform(all, 0.0, 0.0);
+    assert_eq!(settled.translation, Vec3::ZERO);
+    assert!((settled.scale.x - 1.0).abs() < 1e-2);
+}